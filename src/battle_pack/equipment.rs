@@ -0,0 +1,73 @@
+use std::io::{self, Read};
+
+use super::{EQUIPMENT_STRUCT_SIZE, FLAG_BITS, FLYING_FLAG, FLYING_FLAG_OFFSET};
+
+/// One entry of the equipment array. Only the flying flag is understood so far; the rest of the
+/// entry is kept as raw bytes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Equipment([u8; EQUIPMENT_STRUCT_SIZE]);
+
+impl Equipment {
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Equipment> {
+        let mut entry = [0u8; EQUIPMENT_STRUCT_SIZE];
+        reader.read_exact(&mut entry)?;
+        Ok(Equipment(entry))
+    }
+
+    /// Whether the equipment can hit flying enemies.
+    pub fn flying(&self) -> bool {
+        self.0[FLYING_FLAG_OFFSET] & FLYING_FLAG != 0
+    }
+
+    /// The bits set in the flying flag's byte that no flag is known to use. Any at all suggest the entry
+    /// was read from the wrong place.
+    pub fn unknown_flags(&self) -> u8 {
+        self.0[FLYING_FLAG_OFFSET] & !FLAG_BITS
+    }
+
+    /// Each difference from `other` as `(field, old, new)`. The flying flag is reported by name, and any
+    /// other changed byte by its offset within the entry.
+    pub fn changes(&self, other: &Equipment) -> Vec<(String, String, String)> {
+        let mut changes = Vec::new();
+        if self.flying() != other.flying() {
+            changes.push((String::from("flying"), self.flying().to_string(), other.flying().to_string()));
+        }
+        for (offset, (old, new)) in self.0.iter().zip(other.0.iter()).enumerate() {
+            let (old, new) = if offset == FLYING_FLAG_OFFSET { (old & !FLYING_FLAG, new & !FLYING_FLAG) } else { (*old, *new) };
+            if old != new {
+                changes.push((format!("byte 0x{:02x}", offset), format!("0x{:02x}", old), format!("0x{:02x}", new)));
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::battle_pack::{EQUIPMENT_STRUCT_SIZE, FLYING_FLAG_OFFSET};
+    use crate::battle_pack::equipment::Equipment;
+
+    #[test]
+    fn changes_test() {
+        let before = Equipment([0u8; EQUIPMENT_STRUCT_SIZE]);
+        let mut after = before.clone();
+        assert!(before.changes(&after).is_empty());
+
+        after.0[FLYING_FLAG_OFFSET] = 0b101;
+        after.0[12] = 0x40;
+        assert_eq!(before.changes(&after), vec![
+            (String::from("flying"), String::from("false"), String::from("true")),
+            (String::from("byte 0x07"), String::from("0x00"), String::from("0x01")),
+            (String::from("byte 0x0c"), String::from("0x00"), String::from("0x40")),
+        ]);
+    }
+
+    #[test]
+    fn unknown_flags_test() {
+        let mut entry = Equipment([0u8; EQUIPMENT_STRUCT_SIZE]);
+        entry.0[FLYING_FLAG_OFFSET] = 0b101;
+        assert_eq!(entry.unknown_flags(), 0);
+        entry.0[FLYING_FLAG_OFFSET] = 0xc4;
+        assert_eq!(entry.unknown_flags(), 0xc0);
+    }
+}
@@ -1,11 +1,20 @@
+use std::borrow::Cow;
 use std::io::{self, Seek, Read, SeekFrom, Write};
 use byteorder::{ReadBytesExt, LE, WriteBytesExt};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
 
+/// Set on a section's offset in the table when the section is compressed. The game never sets it, as real
+/// offsets are nowhere near 2 GiB.
+const COMPRESSED_FLAG: u32 = 0x8000_0000;
 
 pub struct BattlePackReader<R: Read + Seek> {
     inner: R,
     section_count: usize,
     section_remaining: usize,
+    /// The decompressed contents of the section begun, when it's compressed
+    decompressed: Option<io::Cursor<Vec<u8>>>,
 }
 
 impl<R: Read + Seek> std::fmt::Debug for BattlePackReader<R> {
@@ -31,26 +40,71 @@ impl<R: Read + Seek> BattlePackReader<R> {
             inner,
             section_count,
             section_remaining: 0,
+            decompressed: None,
         })
     }
 
     pub fn section_count(&self) -> usize { self.section_count }
 
+    /// Checks that every section starts after the offset table, no earlier than the section before it,
+    /// and within the pack. A corrupt table otherwise yields sections of the wrong size and contents.
+    pub fn validate(&mut self) -> io::Result<()> {
+        let end = self.inner.seek(SeekFrom::End(0))?;
+        let table_end = size_offset(self.section_count + 1) as u64;
+        let mut previous = table_end;
+        for index in 0..self.section_count {
+            let offset = self.section_offset(index)? as u64;
+            let error = if index == 0 && offset < table_end {
+                format!("section 0 starts at 0x{:x}, inside the offset table ending at 0x{:x}", offset, table_end)
+            } else if offset < previous {
+                format!("section {} starts at 0x{:x}, before section {} at 0x{:x}", index, offset, index - 1, previous)
+            } else if offset > end {
+                format!("section {} starts at 0x{:x}, past the end of the pack at 0x{:x}", index, offset, end)
+            } else {
+                previous = offset;
+                continue;
+            };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+        Ok(())
+    }
+
+    /// The section's size once read, decompressed if it's stored compressed.
     pub fn section_size(&mut self, index: usize) -> io::Result<usize> {
+        if self.is_compressed(index)? {
+            let offset = self.section_offset(index)?;
+            self.inner.seek(SeekFrom::Start(offset as u64))?;
+            Ok(self.inner.read_u32::<LE>()? as usize)
+        } else {
+            self.stored_size(index)
+        }
+    }
+
+    /// The number of bytes the section takes up in the pack.
+    pub fn stored_size(&mut self, index: usize) -> io::Result<usize> {
         assert!(index < self.section_count, "index out of bounds: {} >= {}", index, self.section_count);
         let end = self.inner.seek(SeekFrom::End(0))? as u32;
-        self.inner.seek(SeekFrom::Start(4 + 4 * index as u64))?;
-        let offset = self.inner.read_u32::<LE>()?;
+        let offset = self.section_offset(index)?;
 
-        Ok({
-            let offset2 = self.inner.read_u32::<LE>()?;
-            if index < self.section_count - 1 { offset2 - offset } else {
-                end - offset
-            }
-        } as usize)
+        let size = if index < self.section_count - 1 {
+            self.section_offset(index + 1)? - offset
+        } else {
+            end - offset
+        };
+        Ok(size as usize)
     }
 
     pub fn section_offset(&mut self, index: usize) -> io::Result<u32> {
+        self.raw_offset(index).map(|offset| offset & !COMPRESSED_FLAG)
+    }
+
+    /// Whether the section was written by [`BattlePackWriter::write_section_compressed`]. Compressed sections
+    /// start with their decompressed size as a u32, followed by the deflated data.
+    pub fn is_compressed(&mut self, index: usize) -> io::Result<bool> {
+        self.raw_offset(index).map(|offset| offset & COMPRESSED_FLAG != 0)
+    }
+
+    fn raw_offset(&mut self, index: usize) -> io::Result<u32> {
         assert!(index < self.section_count, "index out of bounds: {} >= {}", index, self.section_count);
         self.inner.seek(SeekFrom::Start(4 + 4 * index as u64))?;
         self.inner.read_u32::<LE>()
@@ -60,11 +114,27 @@ impl<R: Read + Seek> BattlePackReader<R> {
         let offset = self.section_offset(index)? as u64;
         let size = self.section_size(index)?;
         self.section_remaining = size;
+        self.decompressed = None;
+        if self.is_compressed(index)? {
+            let stored = self.stored_size(index)?;
+            self.inner.seek(SeekFrom::Start(offset + 4))?;
+            let mut data = Vec::with_capacity(size);
+            DeflateDecoder::new((&mut self.inner).take(stored.saturating_sub(4) as u64)).read_to_end(&mut data)?;
+            if data.len() != size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("section {} decompressed to {} bytes, not {}", index, data.len(), size)));
+            }
+            self.decompressed = Some(io::Cursor::new(data));
+            return Ok(());
+        }
         self.inner.seek(SeekFrom::Start(offset)).map(|_| ())
     }
 
     pub fn read_section(&mut self, dst: &mut [u8]) -> io::Result<usize> {
-        if self.section_remaining == 0 {
+        if let Some(decompressed) = self.decompressed.as_mut() {
+            let read = decompressed.read(dst)?;
+            self.section_remaining -= read;
+            Ok(read)
+        } else if self.section_remaining == 0 {
             Ok(0)
         } else {
             let read_bytes = if dst.len() > self.section_remaining {
@@ -106,6 +176,33 @@ impl<R: Read + Seek> BattlePackReader<R> {
     }
 }
 
+impl<T: AsRef<[u8]>> BattlePackReader<io::Cursor<T>> {
+    /// Borrows a section straight out of the underlying bytes, without copying it. Compressed sections
+    /// have to be decompressed, and so are copied.
+    pub fn section_bytes(&mut self, index: usize) -> io::Result<Cow<[u8]>> {
+        if self.is_compressed(index)? {
+            let mut data = Vec::new();
+            self.section_begin_to_end(index, &mut data)?;
+            return Ok(Cow::Owned(data));
+        }
+        let offset = self.section_offset(index)? as usize;
+        let size = self.section_size(index)?;
+        self.inner.get_ref().as_ref().get(offset..offset + size)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, format!("section {} runs past the end of the pack", index)))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl BattlePackReader<io::Cursor<memmap::Mmap>> {
+    /// Maps the whole pack into memory, so sections can be sliced out with [`section_bytes`](Self::section_bytes).
+    /// The file must not be modified while it is mapped.
+    pub fn map(file: &std::fs::File) -> io::Result<Self> {
+        let map = unsafe { memmap::Mmap::map(file)? };
+        Self::new(io::Cursor::new(map))
+    }
+}
+
 pub struct BattlePackWriter<W: Write + Seek> {
     inner: W,
     index: usize,
@@ -128,11 +225,33 @@ impl<W: Write + Seek> BattlePackWriter<W> {
     }
 
     pub fn write_section(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_stored(data, 0)
+    }
+
+    /// Writes the section deflated, flagged as compressed in the offset table, unless that wouldn't make it
+    /// any smaller. The game can't read compressed sections, so packs using them are only for this tool.
+    /// Returns whether the section was compressed.
+    pub fn write_section_compressed(&mut self, data: &[u8]) -> io::Result<bool> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(data)?;
+        let deflated = encoder.finish()?;
+        if deflated.len() + 4 >= data.len() {
+            self.write_section(data)?;
+            return Ok(false);
+        }
+        let mut stored = Vec::with_capacity(deflated.len() + 4);
+        stored.write_u32::<LE>(data.len() as u32)?;
+        stored.extend_from_slice(&deflated);
+        self.write_stored(&stored, COMPRESSED_FLAG)?;
+        Ok(true)
+    }
+
+    fn write_stored(&mut self, data: &[u8], flags: u32) -> io::Result<()> {
         if self.index == self.count { return Err(io::ErrorKind::WriteZero.into()) }
         let offset = self.inner.seek(SeekFrom::Current(0))? as u32;
         self.inner.write_all(data)?;
         self.inner.seek(SeekFrom::Start(size_offset(self.index) as u64))?;
-        self.inner.write_u32::<LE>(offset)?;
+        self.inner.write_u32::<LE>(offset | flags)?;
         self.inner.seek(SeekFrom::End(0))?;
         self.index += 1;
         Ok(())
@@ -150,7 +269,7 @@ const fn size_offset(index: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
-    use crate::battle_pack::io::BattlePackWriter;
+    use crate::battle_pack::io::{BattlePackReader, BattlePackWriter};
 
     #[test]
     fn writer_test() {
@@ -163,4 +282,112 @@ mod tests {
         assert_eq!(output.into_inner(), vec![0x47u8, 0, 0, 0, 0x10, 0, 0, 0, 0x14, 0, 0, 0, 0, 0, 0, 0, 0x45, 0x65, 0x99, 0x12, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7])
     }
 
+    /// Layout: 4 byte magic, then a zero terminated table of u32 section offsets, then the sections.
+    /// A section runs up to the next section's offset, or to the end of the pack for the last one.
+    #[test]
+    fn round_trip_test() {
+        let sections: Vec<Vec<u8>> = vec![vec![0x45, 0x65, 0x99, 0x12], vec![0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7], vec![0xff; 40]];
+        let mut writer = BattlePackWriter::new(sections.len(), Cursor::new(Vec::new())).expect("creating writer - writing header");
+        for section in &sections {
+            writer.write_section(section).expect("writing section");
+        }
+        let mut output = writer.into_inner();
+        output.set_position(0);
+
+        let mut reader = BattlePackReader::new(output).expect("creating reader - reading offset table");
+        assert_eq!(reader.section_count(), sections.len());
+        assert_eq!(reader.section_offset(0).expect("reading offset"), 4 + 4 * (sections.len() as u32 + 1));
+        for (i, section) in sections.iter().enumerate() {
+            assert_eq!(reader.section_size(i).expect("reading size"), section.len());
+            let mut read = Vec::new();
+            assert_eq!(reader.section_begin_to_end(i, &mut read).expect("reading section"), section.len());
+            assert_eq!(&read, section);
+        }
+    }
+
+    /// Unpacking and repacking an uncompressed pack has to reproduce it byte for byte.
+    #[test]
+    fn rewrite_is_identical_test() {
+        let sections: Vec<Vec<u8>> = vec![vec![0x10; 9], vec![], vec![0x1, 0x2, 0x3]];
+        let mut writer = BattlePackWriter::new(sections.len(), Cursor::new(Vec::new())).expect("creating writer - writing header");
+        for section in &sections {
+            writer.write_section(section).expect("writing section");
+        }
+        let original = writer.into_inner().into_inner();
+
+        let mut reader = BattlePackReader::new(Cursor::new(original.clone())).expect("creating reader - reading offset table");
+        let mut rewriter = BattlePackWriter::new(reader.section_count(), Cursor::new(Vec::new())).expect("creating writer - writing header");
+        for i in 0..reader.section_count() {
+            let mut section = Vec::new();
+            reader.section_begin_to_end(i, &mut section).expect("reading section");
+            rewriter.write_section(&section).expect("writing section");
+        }
+        assert_eq!(rewriter.into_inner().into_inner(), original);
+    }
+
+    /// Compressed sections have to read back to the bytes written, alongside uncompressed ones.
+    #[test]
+    fn compressed_round_trip_test() {
+        let sections: Vec<Vec<u8>> = vec![vec![0x7; 300], vec![0x1, 0x2, 0x3], (0..=255u8).cycle().take(1000).collect()];
+        let mut writer = BattlePackWriter::new(sections.len(), Cursor::new(Vec::new())).expect("creating writer - writing header");
+        let compressed = sections.iter().map(|section| writer.write_section_compressed(section).expect("writing section")).collect::<Vec<_>>();
+        // Three bytes can't be made any smaller
+        assert_eq!(compressed, vec![true, false, true]);
+        let pack = writer.into_inner().into_inner();
+
+        let mut reader = BattlePackReader::new(Cursor::new(pack)).expect("creating reader - reading offset table");
+        reader.validate().expect("validating a compressed pack");
+        assert_eq!(reader.section_count(), sections.len());
+        for (i, section) in sections.iter().enumerate() {
+            assert_eq!(reader.is_compressed(i).expect("reading offset"), compressed[i]);
+            assert_eq!(reader.section_size(i).expect("reading size"), section.len());
+            let mut read = Vec::new();
+            assert_eq!(reader.section_begin_to_end(i, &mut read).expect("reading section"), section.len());
+            assert_eq!(&read, section);
+            assert_eq!(&*reader.section_bytes(i).expect("slicing section"), &section[..]);
+        }
+        assert!(reader.stored_size(0).expect("reading size") < sections[0].len());
+    }
+
+    #[test]
+    fn validate_test() {
+        let mut writer = BattlePackWriter::new(2, Cursor::new(Vec::new())).expect("creating writer - writing header");
+        writer.write_section(&[0x1, 0x2, 0x3, 0x4]).expect("writing section");
+        writer.write_section(&[0x5, 0x6]).expect("writing section");
+        let mut pack = writer.into_inner().into_inner();
+        BattlePackReader::new(Cursor::new(pack.clone())).expect("creating reader - reading offset table")
+            .validate().expect("validating a well formed pack");
+
+        // Point the second section before the first
+        pack[8] = 0x0c;
+        let err = BattlePackReader::new(Cursor::new(pack)).expect("creating reader - reading offset table")
+            .validate().expect_err("validating overlapping sections");
+        assert_eq!(err.to_string(), "section 1 starts at 0xc, before section 0 at 0x10");
+    }
+
+    #[test]
+    fn section_bytes_test() {
+        let mut writer = BattlePackWriter::new(2, Cursor::new(Vec::new())).expect("creating writer - writing header");
+        writer.write_section(&[0x1, 0x2, 0x3]).expect("writing section");
+        writer.write_section(&[0x4, 0x5]).expect("writing section");
+        let mut reader = BattlePackReader::new(writer.into_inner()).expect("creating reader - reading offset table");
+        assert_eq!(&*reader.section_bytes(0).expect("slicing section"), &[0x1, 0x2, 0x3]);
+        assert_eq!(&*reader.section_bytes(1).expect("slicing section"), &[0x4, 0x5]);
+    }
+
+    #[test]
+    fn partial_read_test() {
+        let mut writer = BattlePackWriter::new(1, Cursor::new(Vec::new())).expect("creating writer - writing header");
+        writer.write_section(&[0x1, 0x2, 0x3, 0x4, 0x5]).expect("writing section");
+        let mut reader = BattlePackReader::new(writer.into_inner()).expect("creating reader - reading offset table");
+
+        reader.begin_section(0).expect("beginning section");
+        let mut buffer = [0u8; 3];
+        assert_eq!(reader.read_section(&mut buffer).expect("reading section"), 3);
+        assert_eq!(buffer, [0x1, 0x2, 0x3]);
+        assert_eq!(reader.read_section(&mut buffer).expect("reading section"), 2);
+        assert_eq!(&buffer[..2], &[0x4, 0x5]);
+        assert_eq!(reader.read_section(&mut buffer).expect("reading section"), 0);
+    }
+
 }
@@ -3,25 +3,32 @@ use std::path::PathBuf;
 #[cfg(feature = "battle_fuse")]
 mod fuse;
 
+mod equipment;
 mod io;
 
+pub use equipment::Equipment;
+pub use io::{BattlePackReader, BattlePackWriter};
+
 use crate::{assert_exists, error_abort};
 use crate::utils;
 use std::fs::{File, OpenOptions, DirBuilder};
 use std::io::{Seek, SeekFrom, Write, Read};
 use byteorder::{ReadBytesExt, WriteBytesExt};
 
-use io::BattlePackReader;
 use walkdir::WalkDir;
-use crate::battle_pack::io::BattlePackWriter;
 use std::str::FromStr;
 
-const EQUIPMENT_SIGNATURE: [u8; 3] = [68, 113, 0];
+/// Known byte sequences preceding the equipment array, tried in order.
+const EQUIPMENT_SIGNATURES: [&[u8]; 1] = [&[68, 113, 0]];
 const OFFSET_FROM_SIGNATURE: usize = 8;
 const FLYING_FLAG_OFFSET: usize = 7;
+const FLYING_FLAG: u8 = 0b100;
+/// The bits of the flying flag's byte used by equipment in known packs.
+const FLAG_BITS: u8 = 0b1111;
 const EQUIPMENT_STRUCT_SIZE: usize = 52;
+const EQUIPMENT_COUNT: usize = 200;
 
-pub fn unpack(battle_pack: PathBuf, output: Option<PathBuf>) {
+pub fn unpack(battle_pack: PathBuf, output: Option<PathBuf>, sections: Option<(usize, usize)>) {
     assert_exists!(battle_pack, "battle pack");
     let output = output.unwrap_or_else(|| battle_pack.with_extension("unpacked"));
 
@@ -36,14 +43,26 @@ pub fn unpack(battle_pack: PathBuf, output: Option<PathBuf>) {
         }
     };
 
-    let mut bp_reader = match BattlePackReader::new(bp_file) {
+    #[cfg(feature = "mmap")]
+    let bp_reader = BattlePackReader::map(&bp_file);
+    #[cfg(not(feature = "mmap"))]
+    let bp_reader = BattlePackReader::new(bp_file);
+    let mut bp_reader = match bp_reader {
         Ok(reader) => reader,
         Err(err) => {
             error_abort!(2, "Failed to create reader over battle pack. Error: {}", err)
         }
     };
+    if let Err(err) = bp_reader.validate() {
+        error_abort!(2, "The battle pack's section table is corrupt: {}", err);
+    }
+
+    let (first, last) = sections.unwrap_or((0, bp_reader.section_count().saturating_sub(1)));
+    if sections.is_some() && last >= bp_reader.section_count() {
+        error_abort!(4, "Section {} is out of range. The battle pack has {} sections.", last, bp_reader.section_count());
+    }
 
-    for i in 0..bp_reader.section_count() {
+    for i in (0..bp_reader.section_count()).filter(|i| (first..=last).contains(i)) {
         let mut output_bin = {
             let out_file_path = output.join(format!("section_{:02}.bin", i));
             let output_path = out_file_path.as_path();
@@ -54,15 +73,12 @@ pub fn unpack(battle_pack: PathBuf, output: Option<PathBuf>) {
                 }
             }
         };
-        let mut buffer = Vec::new();
-        // match bp_reader.section_size(i) {
-        match bp_reader.section_begin_to_end(i, &mut buffer) {
-            Ok(d) => {
-                println!("Exporting section {}, {} bytes.", i, d);
-                if let Err(err) = output_bin.write_all(&buffer) {
+        match read_section(&mut bp_reader, i) {
+            Ok(section) => {
+                println!("Exporting section {}, {} bytes.", i, section.len());
+                if let Err(err) = output_bin.write_all(&section) {
                     error_abort!(4, "Failed to write export for section {}. Error: {}", i, err);
                 }
-                buffer.clear();
             },
             Err(err) => {
                 error_abort!(2, "Failed to read data for section {}. Error: {}", i, err);
@@ -72,7 +88,18 @@ pub fn unpack(battle_pack: PathBuf, output: Option<PathBuf>) {
 
 }
 
-pub fn repack(input_dir: PathBuf, output: PathBuf) {
+#[cfg(feature = "mmap")]
+fn read_section(reader: &mut BattlePackReader<std::io::Cursor<memmap::Mmap>>, index: usize) -> std::io::Result<std::borrow::Cow<[u8]>> {
+    reader.section_bytes(index)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_section(reader: &mut BattlePackReader<File>, index: usize) -> std::io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    reader.section_begin_to_end(index, &mut buffer).map(|_| buffer)
+}
+
+pub fn repack(input_dir: PathBuf, output: PathBuf, validate: bool, compress: bool) {
     if !input_dir.is_dir() { error_abort!(1, "Input directory is nonexistent or is not a directory."); }
     match File::create(output.as_path()) {
         Ok(file) => {
@@ -103,16 +130,77 @@ pub fn repack(input_dir: PathBuf, output: PathBuf) {
                 input.read_to_end(&mut data).unwrap_or_else(|err| error_abort!(1, "Failed to read input file {:?}. Error: {}", entry, err));
                 all_data.push(data);
             }
+            let sizes = all_data.iter().map(Vec::len).collect::<Vec<_>>();
             let mut b_writer = BattlePackWriter::new(all_data.len(), file).unwrap_or_else(|err| error_abort!(2, "Failed to write to output file. Error: {}", err));
+            let mut compressed = 0;
             for (i, section) in all_data.into_iter().enumerate() {
-                b_writer.write_section(&section).unwrap_or_else(|err| error_abort!(2, "Failed to write section {} to output file. Error: {}", i, err))
+                let res = if compress { b_writer.write_section_compressed(&section) } else { b_writer.write_section(&section).map(|_| false) };
+                match res {
+                    Ok(true) => compressed += 1,
+                    Ok(false) => {},
+                    Err(err) => error_abort!(2, "Failed to write section {} to output file. Error: {}", i, err),
+                }
+            }
+            drop(b_writer);
+            if compress {
+                println!("Compressed {} of {} sections.", compressed, sizes.len());
+            }
+            if validate {
+                validate_repack(&output, &sizes);
             }
         },
         Err(err) => { error_abort!(1, "Failed to create output file. Error: {}", err); }
     }
 }
 
-pub fn allow_all_flying(battle_pack: PathBuf) {
+fn validate_repack(output: &PathBuf, sizes: &[usize]) {
+    let file = File::open(output).unwrap_or_else(|err| error_abort!(5, "Failed to reopen output file for validation. Error: {}", err));
+    let mut reader = BattlePackReader::new(file).unwrap_or_else(|err| error_abort!(5, "Failed to read back the written battle pack. Error: {}", err));
+    if let Err(err) = reader.validate() {
+        error_abort!(5, "Validation failed: {}", err);
+    }
+    if reader.section_count() != sizes.len() {
+        error_abort!(5, "Validation failed: wrote {} sections but read back {}.", sizes.len(), reader.section_count());
+    }
+    for (i, &expected) in sizes.iter().enumerate() {
+        let actual = reader.section_size(i).unwrap_or_else(|err| error_abort!(5, "Failed to read back section {}. Error: {}", i, err));
+        if actual != expected {
+            error_abort!(5, "Validation failed: section {} is {} bytes but its input was {} bytes.", i, actual, expected);
+        }
+    }
+    println!("Validated {} sections.", sizes.len());
+}
+
+/// Exits with 6 unless the battle pack has exactly `expected` sections, or with `min` and `max`, a count
+/// within those bounds.
+pub fn assert_sections(battle_pack: PathBuf, expected: Option<usize>, min: Option<usize>, max: Option<usize>) {
+    assert_exists!(battle_pack, "battle pack");
+    let (min, max) = match expected {
+        Some(expected) => (Some(expected), Some(expected)),
+        None => (min, max),
+    };
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max { error_abort!(1, "--min {} is greater than --max {}.", min, max); }
+    }
+
+    let file = File::open(&battle_pack).unwrap_or_else(|err| error_abort!(1, "Failed to open battle pack '{:?}' for reading. Error: {}", &battle_pack, err));
+    let reader = BattlePackReader::new(file).unwrap_or_else(|err| error_abort!(2, "Failed to create reader over battle pack. Error: {}", err));
+    let actual = reader.section_count();
+
+    let expectation = match (min, max) {
+        (Some(min), Some(max)) if min == max => format!("exactly {}", min),
+        (Some(min), Some(max)) => format!("between {} and {}", min, max),
+        (Some(min), None) => format!("at least {}", min),
+        (None, Some(max)) => format!("at most {}", max),
+        (None, None) => unreachable!("structopt requires --expected, --min or --max"),
+    };
+    if min.map_or(false, |min| actual < min) || max.map_or(false, |max| actual > max) {
+        error_abort!(6, "The battle pack has {} sections, expected {}.", actual, expectation);
+    }
+    println!("The battle pack has {} sections, as expected.", actual);
+}
+
+pub fn allow_all_flying(battle_pack: PathBuf, signature: Option<String>, force: bool) {
     assert_exists!(battle_pack, "battle pack");
     let mut options = OpenOptions::new();
     options.read(true).write(true);
@@ -123,21 +211,97 @@ pub fn allow_all_flying(battle_pack: PathBuf) {
             std::process::exit(-1);
         }
     };
-    let equip_array = match utils::locate_signature(&mut file, &EQUIPMENT_SIGNATURE[..]) {
-        Some(loc) => loc + OFFSET_FROM_SIGNATURE,
+    let equip_array = locate_equipment(&mut file, signature.as_deref());
+    // Everything below assumes the array starts OFFSET_FROM_SIGNATURE bytes after the signature, so check the
+    // first entry looks like equipment before writing to all of them.
+    file.seek(SeekFrom::Start(equip_array as u64)).expect("Seeking file");
+    let first = Equipment::read(&mut file).expect("Reading file");
+    if first.unknown_flags() != 0 && !force {
+        eprintln!("The first equipment entry at 0x{:x} sets unexpected flag bits (0x{:02x}).", equip_array, first.unknown_flags());
+        eprintln!("The equipment array is likely not {} bytes after the signature; no changes were made. Use --force to write anyway.", OFFSET_FROM_SIGNATURE);
+        std::process::exit(7);
+    }
+    utils::defer_interrupts();
+    let mut changed = 0;
+    for id in (0usize..EQUIPMENT_COUNT).map(|a| a * EQUIPMENT_STRUCT_SIZE + equip_array + FLYING_FLAG_OFFSET) {
+        file.seek(SeekFrom::Start(id as u64)).expect("Seeking file");
+        let byte = file.read_u8().expect("Reading file");
+        if byte & FLYING_FLAG == 0 {
+            file.seek(SeekFrom::Start(id as u64)).expect("Seeking file");
+            file.write_u8(byte | FLYING_FLAG).expect("Writing file");
+            changed += 1;
+        }
+        if utils::interrupted() {
+            eprintln!("Interrupted after changing {} entries. The battle pack has only been partially modified.", changed);
+            std::process::exit(utils::INTERRUPTED_EXIT_CODE);
+        }
+    }
+
+    println!("Made all weapons in battle pack able to hit flying enemies. {} of {} entries changed.", changed, EQUIPMENT_COUNT);
+
+}
+
+/// Finds where the equipment array starts, using `signature` (as hex) or else each known signature,
+/// exiting if it can't be found or wouldn't fit in the pack.
+fn locate_equipment<R: Read + Seek>(file: &mut R, signature: Option<&str>) -> usize {
+    let candidates = match signature {
+        Some(hex_sig) => match hex::decode(hex_sig.trim()) {
+            Ok(bytes) if bytes.len() > 1 => vec![bytes],
+            Ok(_) => error_abort!(1, "The equipment signature must be at least 2 bytes long."),
+            Err(err) => error_abort!(1, "Invalid equipment signature '{}'. Error: {}", hex_sig, err),
+        },
+        None => EQUIPMENT_SIGNATURES.iter().map(|sig| sig.to_vec()).collect(),
+    };
+    let located = candidates.iter().find_map(|sig| {
+        file.seek(SeekFrom::Start(0)).expect("Seeking file");
+        utils::locate_signature(&mut *file, sig).map(|loc| (loc, sig))
+    });
+    let equip_array = match located {
+        Some((loc, sig)) => {
+            println!("Located appropriate section using signature {}.", hex::encode(sig));
+            loc + OFFSET_FROM_SIGNATURE
+        },
         None => {
             eprintln!("Unable to find the equipment section within the battle pack.");
             std::process::exit(7);
         }
     };
-    println!("Located appropriate section.");
-    for id in (0usize..=199).map(|a| a * EQUIPMENT_STRUCT_SIZE + equip_array + FLYING_FLAG_OFFSET) {
-        file.seek(SeekFrom::Start(id as u64)).expect("Seeking file");
-        let byte = file.read_u8().expect("Reading file");
-        file.seek(SeekFrom::Start(id as u64)).expect("Seeking file");
-        file.write_u8(byte | 0b100).expect("Writing file");
+    let file_len = file.seek(SeekFrom::End(0)).expect("Seeking file") as usize;
+    let equip_end = equip_array + EQUIPMENT_COUNT * EQUIPMENT_STRUCT_SIZE;
+    if equip_end > file_len {
+        eprintln!("The equipment section would end at 0x{:x}, past the end of the battle pack (0x{:x}).", equip_end, file_len);
+        eprintln!("The signature likely matched the wrong location; no changes were made.");
+        std::process::exit(7);
     }
+    equip_array
+}
+
+/// Reads every entry of the equipment array starting at `equip_array`.
+fn read_equipment<R: Read + Seek>(file: &mut R, equip_array: usize) -> std::io::Result<Vec<Equipment>> {
+    file.seek(SeekFrom::Start(equip_array as u64))?;
+    (0..EQUIPMENT_COUNT).map(|_| Equipment::read(file)).collect()
+}
 
-    println!("Made all weapons in battle pack able to hit flying enemies.");
+/// Prints each equipment entry that differs between two battle packs, field by field.
+pub fn diff_equipment(a: PathBuf, b: PathBuf, signature: Option<String>) {
+    assert_exists!(a, "battle pack");
+    assert_exists!(b, "battle pack");
+    let read = |path: &PathBuf| {
+        let mut file = File::open(path).unwrap_or_else(|err| error_abort!(1, "Failed to open battle pack {:?}. Error: {}", path, err));
+        let equip_array = locate_equipment(&mut file, signature.as_deref());
+        read_equipment(&mut file, equip_array).unwrap_or_else(|err| error_abort!(2, "Failed to read the equipment of {:?}. Error: {}", path, err))
+    };
+    let (before, after) = (read(&a), read(&b));
 
+    let mut differing = 0;
+    for (i, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+        let changes = old.changes(new);
+        if changes.is_empty() { continue; }
+        differing += 1;
+        println!("Entry {}", i);
+        for (field, old, new) in changes {
+            println!("\t{}: {} -> {}", field, old, new);
+        }
+    }
+    println!("{} of {} entries differ.", differing, EQUIPMENT_COUNT);
 }
@@ -3,19 +3,25 @@ use std::fmt::Error as FmtError;
 use std::fmt::Formatter;
 use std::io::Error as IOError;
 
+use bincode::Error as BincodeError;
 use serde_json::Error as JsonError;
 
 #[derive(Debug)]
 pub enum TreasureError {
     IO(IOError),
-    JSON(JsonError)
+    JSON(JsonError),
+    Bincode(BincodeError),
+    /// A row of a CSV data file that couldn't be used, by its line number
+    Csv(usize, String),
 }
 
 impl Display for TreasureError {
     fn fmt(&self, f: &mut Formatter) -> Result<(), FmtError> {
         match self {
             Self::IO(io) => write!(f, "An IO error occurred: {}", io),
-            Self::JSON(json) => write!(f, "A JSON error occurred: {}", json)
+            Self::JSON(json) => write!(f, "A JSON error occurred: {}", json),
+            Self::Bincode(bincode) => write!(f, "A bincode error occurred: {}", bincode),
+            Self::Csv(line, msg) => write!(f, "Bad CSV row on line {}: {}", line, msg),
         }
     }
 }
@@ -29,3 +35,7 @@ impl From<IOError> for TreasureError {
 impl From<JsonError> for TreasureError {
     fn from(json: JsonError) -> Self { TreasureError::JSON(json) }
 }
+
+impl From<BincodeError> for TreasureError {
+    fn from(bincode: BincodeError) -> Self { TreasureError::Bincode(bincode) }
+}
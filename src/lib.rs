@@ -0,0 +1,31 @@
+mod error;
+pub mod opt;
+pub mod treasure;
+pub mod magick_order;
+pub mod battle_pack;
+mod utils;
+pub mod vbf;
+pub mod prelude;
+
+use std::io::Write;
+
+#[macro_export]
+macro_rules! assert_exists {
+    ($file:expr, $desc:expr) => {
+        if !$file.exists() { eprintln!("Missing {} file", $desc); std::process::exit(1); }
+    };
+}
+
+#[macro_export]
+macro_rules! error_abort {
+    ($code:expr) => { error_exit($code, format_args!()); };
+    ($code:expr, $($arg:tt)*) => { $crate::error_exit($code, format_args!($($arg)*)); };
+}
+
+#[doc(hidden)]
+#[inline]
+pub fn error_exit(code: i32, args: std::fmt::Arguments) -> ! {
+    std::io::stderr().write_fmt(args).expect("Writing to stderr");
+    std::io::stderr().write(&['\n' as u8]).expect("Writing to stderr");
+    std::process::exit(code);
+}
@@ -1,42 +1,41 @@
-mod error;
-mod opt;
-mod treasure;
-mod magick_order;
-mod battle_pack;
-mod utils;
-mod vbf;
-
+use ff12tza_utils::{battle_pack, magick_order, opt, treasure, vbf};
 use opt::Opts;
 use structopt::StructOpt;
-use std::io::Write;
-
-#[macro_export]
-macro_rules! assert_exists {
-    ($file:expr, $desc:expr) => {
-        if !$file.exists() { eprintln!("Missing {} file", $desc); std::process::exit(1); }
-    };
-}
-
-#[macro_export]
-macro_rules! error_abort {
-    ($code:expr) => { error_exit($code, format_args!()); };
-    ($code:expr, $($arg:tt)*) => { $crate::error_exit($code, format_args!($($arg)*)); };
-}
-
-#[inline]
-fn error_exit(code: i32, args: std::fmt::Arguments) -> ! {
-    std::io::stderr().write_fmt(args).expect("Writing to stderr");
-    std::io::stderr().write(&['\n' as u8]).expect("Writing to stderr");
-    std::process::exit(code);
-}
 
 fn main() {
     let opts: Opts = Opts::from_args();
     match opts {
-        Opts::DumpTreasure { create_maps, input, output, treasure_data, item_data } => treasure::dump_treasure(input, output, treasure_data, item_data, create_maps),
+        Opts::DumpTreasure(dump) => treasure::dump_treasure(dump),
         Opts::ReorderMagick { battle_pack, magick_order, output } => magick_order::reorder_magick(battle_pack, magick_order, output),
+        Opts::Treasure(t) => match_treasure(t),
         Opts::BattlePack(bp) => match_battle_pack(bp),
+        Opts::ItemData(item_data) => match_item_data(item_data),
         Opts::VBF(vbf) => match_vbf(vbf),
+        Opts::SelfTest => treasure::self_test(),
+    }
+}
+
+fn match_treasure(opts: opt::Treasure) {
+    match opts {
+        opt::Treasure::UsedItems { input, treasure_data, item_data, item_format, format, no_cache } => treasure::used_items(input, treasure_data, item_data, item_format, format, no_cache),
+        opt::Treasure::ScaffoldItems { input, treasure_data, output, no_cache } => treasure::scaffold_items(input, treasure_data, output, no_cache),
+        opt::Treasure::Load(dump) => treasure::load_treasure(dump),
+        opt::Treasure::RespawnSlots { input, treasure_data, item_data, item_format, format, hide_empty_slots, no_cache } => treasure::respawn_slots(input, treasure_data, item_data, item_format, format, hide_empty_slots, no_cache),
+        opt::Treasure::DiffVanilla { input, vanilla, treasure_data, item_data, item_format, no_cache } => treasure::diff_vanilla(input, vanilla, treasure_data, item_data, item_format, no_cache),
+        opt::Treasure::Find { input, id, zone, treasure_data, item_data, item_format, format, no_cache } => treasure::find(input, id, zone, treasure_data, item_data, item_format, format, no_cache),
+        opt::Treasure::Near { input, zone, x, y, radius, treasure_data, item_data, item_format, format, precision, no_cache } => treasure::near(input, zone, x, y, radius, treasure_data, item_data, item_format, format, precision, no_cache),
+        opt::Treasure::Route { input, zone, start, svg, treasure_data, item_data, item_format, format, precision, no_cache } => treasure::route(input, zone, start, svg, treasure_data, item_data, item_format, format, precision, no_cache),
+        opt::Treasure::Simulate { input, zone, id, trials, seed, diamond_armlet, profile, treasure_data, item_data, item_format, format, precision, no_cache } => treasure::simulate(input, zone, id, trials, seed, diamond_armlet, profile, treasure_data, item_data, item_format, format, precision, no_cache),
+        opt::Treasure::Inspect { file, bytes, min_records } => treasure::inspect(file, bytes, min_records),
+        opt::Treasure::Stats { input, treasure_data, item_data, item_format, top, format, no_cache } => treasure::stats(input, treasure_data, item_data, item_format, top, format, no_cache),
+        #[cfg(feature = "tui_browse")]
+        opt::Treasure::Browse { input, treasure_data, item_data, item_format, profile, precision, no_cache } => treasure::browse(input, treasure_data, item_data, item_format, profile, precision, no_cache),
+    }
+}
+
+fn match_item_data(opts: opt::ItemData) {
+    match opts {
+        opt::ItemData::Diff { a, b, format } => treasure::diff_item_data(a, b, format),
     }
 }
 
@@ -50,9 +49,11 @@ fn match_vbf(opts: opt::Vbf) {
 #[allow(unused)]
 fn match_battle_pack(opts: opt::BattlePack) {
     match opts {
-        opt::BattlePack::Unpack {battle_pack, output} => { battle_pack::unpack(battle_pack, output); },
-        opt::BattlePack::Repack {input, battle_pack} => { battle_pack::repack(input, battle_pack); },
-        opt::BattlePack::AllowAllFlying {battle_pack} => {},
+        opt::BattlePack::Unpack {battle_pack, output, sections} => { battle_pack::unpack(battle_pack, output, sections); },
+        opt::BattlePack::Repack {input, battle_pack, validate, compress} => { battle_pack::repack(input, battle_pack, validate, compress); },
+        opt::BattlePack::AllowAllFlying {..} => {},
+        opt::BattlePack::DiffEquipment { a, b, signature } => battle_pack::diff_equipment(a, b, signature),
+        opt::BattlePack::AssertSections { battle_pack, expected, min, max } => battle_pack::assert_sections(battle_pack, expected, min, max),
         opt::BattlePack::Fuse { battle_pack, mount_point } => {}
     }
 }
@@ -61,9 +62,11 @@ fn match_battle_pack(opts: opt::BattlePack) {
 #[allow(unused)]
 fn match_battle_pack(opts: opt::BattlePack) {
     match opts {
-        opt::BattlePack::Unpack {battle_pack, output} => { battle_pack::unpack(battle_pack, output); },
-        opt::BattlePack::Repack {input, battle_pack} => { battle_pack::repack(input, battle_pack); },
-        opt::BattlePack::AllowAllFlying {battle_pack} => battle_pack::allow_all_flying(battle_pack),
+        opt::BattlePack::Unpack {battle_pack, output, sections} => { battle_pack::unpack(battle_pack, output, sections); },
+        opt::BattlePack::Repack {input, battle_pack, validate, compress} => { battle_pack::repack(input, battle_pack, validate, compress); },
+        opt::BattlePack::AllowAllFlying {battle_pack, signature, force} => battle_pack::allow_all_flying(battle_pack, signature, force),
+        opt::BattlePack::DiffEquipment { a, b, signature } => battle_pack::diff_equipment(a, b, signature),
+        opt::BattlePack::AssertSections { battle_pack, expected, min, max } => battle_pack::assert_sections(battle_pack, expected, min, max),
         #[allow(unreachable_patterns)]
         _ => unreachable!()
     }
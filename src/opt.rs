@@ -1,25 +1,14 @@
 
 use structopt::StructOpt;
+use std::collections::HashSet;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Utilities for FFXII: TZA modding")]
 pub enum Opts {
     /// Dump all treasure info (position, contents, chances, etc.)
-    DumpTreasure {
-        /// Whether to dump .svg maps of the obtained treasure info
-        #[structopt(long, requires_all(&["output"]))]
-        create_maps: bool,
-        #[structopt(parse(from_os_str))]
-        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
-        input: PathBuf,
-        #[structopt(parse(from_os_str))]
-        output: Option<PathBuf>,
-        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
-        treasure_data: PathBuf,
-        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
-        item_data: PathBuf,
-    },
+    DumpTreasure(DumpTreasure),
     /// Reorder the magick sort list in the battle pack
     ReorderMagick {
         #[structopt(parse(from_os_str))]
@@ -29,11 +18,792 @@ pub enum Opts {
         #[structopt(parse(from_os_str))]
         output: PathBuf,
     },
+    /// Queries and reports over the parsed treasure data
+    Treasure(Treasure),
     /// Utilities for unpacking the battle pack
     BattlePack(BattlePack),
+    /// Utilities for item data files
+    ItemData(ItemData),
     /// Utilities regarding the .VBF file
     VBF(Vbf),
+    /// Dump embedded sample data and check the output, to confirm the build works
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    SelfTest,
+}
+#[derive(StructOpt, Debug)]
+pub struct DumpTreasure {
+    /// Whether to dump .svg maps of the obtained treasure info. Needs an output
+    #[structopt(long)]
+    pub create_maps: bool,
+    #[structopt(parse(from_os_str), required_unless = "input-file")]
+    /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+    /// Use - to read a list of zone script paths from stdin instead.
+    /// Left out with --input-file, in which case a single path given is the output
+    pub input: Option<PathBuf>,
+    /// The directory to write each zone's output to, or a .zip archive to write it all into instead
+    #[structopt(parse(from_os_str))]
+    pub output: Option<PathBuf>,
+    /// The output, given as an option instead of after the input
+    #[structopt(short = "o", long = "output", parse(from_os_str), conflicts_with = "output")]
+    pub output_option: Option<PathBuf>,
+    #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+    pub treasure_data: PathBuf,
+    #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+    pub item_data: PathBuf,
+    /// Find the treasure data in this directory by its conventional name, instead of --treasure-data
+    #[structopt(long, parse(from_os_str))]
+    pub treasure_data_dir: Option<PathBuf>,
+    /// Find the item data in this directory by its conventional name, instead of --item-data.
+    /// That's item_data.json or items.json, or with --item-format gametext, item_names.bin or items.bin
+    #[structopt(long, parse(from_os_str))]
+    pub item_data_dir: Option<PathBuf>,
+    /// The format of the item data. (json, gametext)
+    /// Gametext is the game's item name resource: null terminated names, one per item ID in order.
+    #[structopt(long, default_value = "json")]
+    pub item_format: ItemFormat,
+    /// Also draw each group's zones together on one map, placed by the world offsets in the zone data.
+    /// Written to the output directory as <group>.svg, so needs an output. Zones without a world offset are left out.
+    #[structopt(long)]
+    pub merge_maps: bool,
+    /// Assemble every zone map into a single HTML document at the given path
+    #[structopt(long, parse(from_os_str))]
+    pub maps_html: Option<PathBuf>,
+    /// Omit the column titles and rule line from the text table, emitting only the zone name and rows
+    #[structopt(long)]
+    pub no_header: bool,
+    /// Skip the on-disk cache of parsed treasure and item data files
+    #[structopt(long)]
+    pub no_cache: bool,
+    /// Draw larger Y positions towards the top of the generated maps
+    #[structopt(long)]
+    pub flip_y: bool,
+    /// Factor to multiply treasure positions by on the generated maps
+    #[structopt(long, default_value = "1.0")]
+    pub scale: f64,
+    /// Amount to shift treasure positions by on the generated maps, given as x,y
+    #[structopt(long, parse(try_from_str = parse_offset), default_value = "0,0", allow_hyphen_values = true)]
+    pub offset: (f64, f64),
+    /// Write every warning raised during the dump to the given path as JSON
+    #[structopt(long, parse(from_os_str))]
+    pub warnings_json: Option<PathBuf>,
+    /// Write the respawn slots to the given path instead of into the output, or to stdout with -
+    #[structopt(long, parse(from_os_str))]
+    pub slots_output: Option<PathBuf>,
+    /// Order each zone's treasures by the given key instead of file order. (rarity, position)
+    /// Position orders by Y, then X, so the table roughly follows the map top to bottom.
+    /// Both sorts are stable, so treasures with the same key keep their file order.
+    #[structopt(long)]
+    pub sort_by: Option<SortBy>,
+    /// A JSON profile overriding game-version assumptions such as the item slot split percentages
+    #[structopt(long, parse(from_os_str))]
+    pub profile: Option<PathBuf>,
+    /// Divide raw positions by this to give world units, for when they're stored as fixed-point. Overrides
+    /// the profile's position_scale. Applies to the table, JSON, GeoJSON, maps and --map-region, but not bincode or SQLite
+    #[structopt(long)]
+    pub position_scale: Option<f64>,
+    /// Normalize each zone's positions to 0..1 across its bounding box, on maps and as extra table columns
+    #[structopt(long)]
+    pub relative_positions: bool,
+    /// The format of each zone's output. (text, json, canonical-json, geojson, bincode, checklist, lua, sqlite)
+    /// Several can be given separated by commas, such as text,json, to write each zone in all of them from one pass.
+    /// JSON gives each item as an object with its ID and name, the name being null for IDs missing from the item data,
+    /// and writes the respawn slots as respawn-slots.json, along with the free slots no chest uses.
+    /// Canonical JSON is the same, but with sorted keys and treasures in ID order so identical data always gives identical bytes.
+    /// Bincode writes every zone to a single treasures.bin snapshot instead.
+    /// Checklist writes a Markdown task list of each zone's chests and their contents, for ticking off opened chests.
+    /// Lua writes each zone as a script returning a table of its treasures keyed by the zone name, with the same fields as JSON.
+    /// SQLite writes every zone to the database file named by the output, in zones, items and treasures tables.
+    /// It's only available when built with --features sqlite.
+    #[structopt(long, default_value = "text", use_delimiter = true)]
+    pub format: Vec<OutputFormat>,
+    /// Shape of the treasure markers on the generated maps. (circle, square, diamond, triangle)
+    #[structopt(long, default_value = "circle")]
+    pub marker: MarkerShape,
+    /// Radius of the treasure markers on the generated maps, in pixels
+    #[structopt(long, default_value = "5")]
+    pub marker_size: u32,
+    /// Draw a route through each zone's chests on the maps. (nearest, optimized)
+    /// Nearest starts at the first chest and always heads to the closest unvisited one.
+    #[structopt(long)]
+    pub route: Option<RouteMode>,
+    /// Only map the part of each zone within this rectangle of world positions, given as x0,y0,x1,y1.
+    /// Chests outside it are left off, and counted in the map's corner. Merged maps are unaffected
+    #[structopt(long, parse(try_from_str = parse_map_region), allow_hyphen_values = true)]
+    pub map_region: Option<(i16, i16, i16, i16)>,
+    /// Mark items whose only source chests are all within the zone's group.
+    /// Marked with a leading * in text output, and listed as exclusive_items in JSON and GeoJSON.
+    #[structopt(long)]
+    pub exclusive_loot: bool,
+    /// Stop after dumping this many zones
+    #[structopt(long)]
+    pub limit: Option<usize>,
+    /// Shown in place of items whose name in the item data is empty, typically "no item" entries
+    #[structopt(long, default_value = "(none)")]
+    pub empty_name: String,
+    /// Skip every zone in this group. Can be given more than once
+    #[structopt(long)]
+    pub exclude_group: Vec<String>,
+    /// Skip this zone, by file name or zone name. Can be given more than once
+    #[structopt(long)]
+    pub exclude_zone: Vec<String>,
+    /// Group, and so directory, for zones not listed in any group. Empty places them in the output root
+    #[structopt(long, default_value = "Unknown")]
+    pub unknown_group: String,
+    /// Also write each treasure's source record as hex, into a .raw.txt file beside the zone's output
+    #[structopt(long)]
+    pub dump_raw_bytes: bool,
+    /// Also write each zone's records as unbroken hex into a .raw.json file beside its output, keyed by treasure ID,
+    /// for tools that want every byte including the ones parsing skips. Needs an output
+    #[structopt(long)]
+    pub raw_sidecar: bool,
+    /// Comma separated item IDs, decimal or 0x prefixed hex. Chests that can drop any of them are
+    /// starred (or colored, on a terminal) in text output and ringed on maps
+    #[structopt(long, parse(try_from_str = parse_item_ids))]
+    pub highlight: Option<HashSet<u16>>,
+    /// When to color terminal output. (auto, always, never)
+    /// Auto colors only when the output is a terminal and NO_COLOR isn't set.
+    #[structopt(long, default_value = "auto")]
+    pub color: ColorChoice,
+    /// Color each text table row by how likely the chest's likeliest drop is on a check, from green to red.
+    /// Only applies when the text goes to a terminal and --color allows it. Highlighted rows keep their highlight
+    #[structopt(long)]
+    pub colorize: bool,
+    /// End lines in text output with \r\n, for viewing on Windows. JSON and SVG output is unaffected
+    #[structopt(long)]
+    pub crlf: bool,
+    /// Leave chests with a 0% spawn chance out of every output, as they never appear in game
+    #[structopt(long)]
+    pub hide_unspawnable: bool,
+    /// Append to existing zone files instead of replacing them, for text and JSON output.
+    /// Text appended to a file that isn't empty skips the zone name and column header.
+    #[structopt(long)]
+    pub append: bool,
+    /// Exit with an error instead of a warning when the input has no zone files
+    #[structopt(long)]
+    pub strict: bool,
+    /// Decimal places for computed values in text output, such as item chances and relative positions.
+    /// JSON, Lua and the other structured formats keep full precision
+    #[structopt(long, default_value = "2")]
+    pub precision: usize,
+    /// Dump again whenever the input or either data file changes
+    #[structopt(long)]
+    pub watch: bool,
+    /// Dump exactly the zone scripts listed in this file, one path per line, in order.
+    /// Use - to read the list from stdin
+    #[structopt(long, parse(from_os_str))]
+    pub files_from: Option<PathBuf>,
+    /// Dump the files under the input directory matching this glob, such as **/B*.ebp, instead of every .ebp file
+    #[structopt(long, conflicts_with = "files-from")]
+    pub input_glob: Option<String>,
+    /// Dump just this zone script, named by --zone, instead of an input directory
+    #[structopt(long, parse(from_os_str), requires = "zone", conflicts_with_all = &["files-from", "input-glob"])]
+    pub input_file: Option<PathBuf>,
+    /// Name of the --input-file zone, which its offset and quantity are looked up in the treasure data by
+    #[structopt(long, requires = "input-file")]
+    pub zone: Option<String>,
+    /// Offset of the --input-file zone's treasure table, used instead of the treasure data's
+    #[structopt(long, requires = "input-file")]
+    pub table_offset: Option<u64>,
+    /// Number of treasures in the --input-file zone, used instead of the treasure data's
+    #[structopt(long, requires = "input-file")]
+    pub quantity: Option<u16>,
+    /// Collapse item slots that repeat: a chest with one item in every slot shows it once as
+    /// guaranteed, and Diamond Armlet slots matching the common ones show as (same)
+    #[structopt(long)]
+    pub fold_identical: bool,
+    /// Only list chests whose 5% Diamond Armlet item isn't one of their common items, least likely first,
+    /// with the overall chance of getting it. Text shows just that item; JSON adds rare_chance
+    #[structopt(long)]
+    pub rare_focus: bool,
+    /// Collapse each chest's item slots into the distinct items it can give, plus gil, with the chance of each
+    /// once it's opened, with and without the Diamond Armlet. Text lists those in place of the table; JSON adds loot_set
+    #[structopt(long, conflicts_with = "rare-focus")]
+    pub loot_set: bool,
+    /// Leave respawn slots no chest is bound to out of respawn-slots.txt
+    #[structopt(long)]
+    pub hide_empty_slots: bool,
+    /// Tidy item names for display: trim them and collapse repeated whitespace. The item data is unchanged
+    #[structopt(long)]
+    pub pretty_names: bool,
+    /// With --pretty-names, also title-case names written entirely in capitals, such as HI-POTION
+    #[structopt(long, requires = "pretty-names")]
+    pub title_case: bool,
+    /// Show gil as one range from the common to the Diamond Armlet amount, such as 120–500, in place of
+    /// the Gil and DGil columns. JSON and GeoJSON give gil as { common, rare } instead
+    #[structopt(long)]
+    pub gil_range: bool,
+    /// Warn about chests at exactly the same position as another, and badge them on maps with how many share it
+    #[structopt(long)]
+    pub warn_shared_positions: bool,
+    /// Warn about chests that can give an item, having a gil chance under 100%, but hold item ID 0 in a slot that can be picked
+    #[structopt(long)]
+    pub warn_empty_items: bool,
+    /// List the zones that would be dumped, their output paths and treasure counts, without reading
+    /// the zone scripts or writing anything
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Time parsing and map rendering for each zone, and print the slowest zones and the totals at the end
+    #[structopt(long)]
+    pub timing: bool,
+    /// A shell command each zone is passed through before it's written. It gets the zone as JSON on stdin,
+    /// {"group", "name", "treasures"} with the treasures' raw fields, and must write the same back to stdout
+    #[structopt(long)]
+    pub filter_cmd: Option<String>,
+}
+
+impl DumpTreasure {
+    /// The input directory, or the zone script given with --input-file.
+    pub fn input(&self) -> &PathBuf {
+        self.input_file.as_ref().or_else(|| self.input.as_ref()).expect("An input is required")
+    }
+
+    pub fn has_format(&self, format: OutputFormat) -> bool {
+        self.format.contains(&format)
+    }
+
+    /// Whether the dump goes to a database rather than files per zone.
+    pub fn is_database(&self) -> bool {
+        self.format.iter().any(|format| format.is_database())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MarkerShape {
+    Circle,
+    Square,
+    Diamond,
+    Triangle,
+}
+
+impl MarkerShape {
+    /// The shape used to set a marker apart from ones drawn with this shape.
+    pub fn alternate(self) -> MarkerShape {
+        match self {
+            MarkerShape::Circle => MarkerShape::Triangle,
+            _ => MarkerShape::Circle,
+        }
+    }
+}
+
+impl FromStr for MarkerShape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "circle" => Ok(MarkerShape::Circle),
+            "square" => Ok(MarkerShape::Square),
+            "diamond" => Ok(MarkerShape::Diamond),
+            "triangle" => Ok(MarkerShape::Triangle),
+            _ => Err(format!("Unknown marker shape '{}'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    CanonicalJson,
+    GeoJson,
+    Bincode,
+    Checklist,
+    Lua,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json | OutputFormat::CanonicalJson => "json",
+            OutputFormat::GeoJson => "geojson",
+            OutputFormat::Bincode => "bin",
+            OutputFormat::Checklist => "md",
+            OutputFormat::Lua => "lua",
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => "db",
+        }
+    }
+
+    /// Whether the output is a single database file rather than a directory.
+    pub fn is_database(self) -> bool {
+        #[cfg(feature = "sqlite")]
+        { self == OutputFormat::Sqlite }
+        #[cfg(not(feature = "sqlite"))]
+        { false }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "canonical-json" => Ok(OutputFormat::CanonicalJson),
+            "geojson" => Ok(OutputFormat::GeoJson),
+            "bincode" => Ok(OutputFormat::Bincode),
+            "checklist" => Ok(OutputFormat::Checklist),
+            "lua" => Ok(OutputFormat::Lua),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            #[cfg(not(feature = "sqlite"))]
+            "sqlite" => Err(String::from("SQLite output needs a build with --features sqlite")),
+            _ => Err(format!("Unknown output format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SortBy {
+    /// Rarest item tier first, using the item data's rarity map
+    Rarity,
+    /// By Y, then X position
+    Position,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rarity" => Ok(SortBy::Rarity),
+            "position" => Ok(SortBy::Position),
+            _ => Err(format!("Unknown sort key '{}'", s)),
+        }
+    }
+}
+
+fn parse_offset(src: &str) -> Result<(f64, f64), String> {
+    let (x, y) = match src.find(',') {
+        Some(i) => (&src[..i], &src[i + 1..]),
+        None => return Err(format!("Expected an offset in the form x,y but got '{}'", src)),
+    };
+    let x = x.trim().parse::<f64>().map_err(|err| format!("Invalid x offset '{}': {}", x, err))?;
+    let y = y.trim().parse::<f64>().map_err(|err| format!("Invalid y offset '{}': {}", y, err))?;
+    Ok((x, y))
+}
+
+fn parse_map_region(src: &str) -> Result<(i16, i16, i16, i16), String> {
+    let corners = src.split(',').map(|value| value.trim().parse::<i16>().map_err(|err| format!("Invalid map region coordinate '{}': {}", value, err)))
+        .collect::<Result<Vec<_>, _>>()?;
+    match corners.as_slice() {
+        [x0, y0, x1, y1] => Ok((*x0, *y0, *x1, *y1)),
+        _ => Err(format!("Expected a map region in the form x0,y0,x1,y1 but got '{}'", src)),
+    }
+}
+
+fn parse_section_range(src: &str) -> Result<(usize, usize), String> {
+    let (start, end) = match src.find('-') {
+        Some(i) => (&src[..i], &src[i + 1..]),
+        None => (src, src),
+    };
+    let start = start.trim().parse::<usize>().map_err(|err| format!("Invalid first section '{}': {}", start, err))?;
+    let end = end.trim().parse::<usize>().map_err(|err| format!("Invalid last section '{}': {}", end, err))?;
+    if start > end {
+        return Err(format!("Section range '{}' ends before it starts", src));
+    }
+    Ok((start, end))
+}
+
+fn parse_item_ids(src: &str) -> Result<HashSet<u16>, String> {
+    src.split(',').map(|id| {
+        let id = id.trim();
+        let res = if id.starts_with("0x") { u16::from_str_radix(&id[2..], 16) } else { id.parse::<u16>() };
+        res.map_err(|err| format!("Invalid item ID '{}': {}", id, err))
+    }).collect()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ItemFormat {
+    Json,
+    GameText,
+}
+
+impl FromStr for ItemFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ItemFormat::Json),
+            "gametext" => Ok(ItemFormat::GameText),
+            _ => Err(format!("Unknown item format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether output to `stream` should be colored.
+    pub fn enabled(self, stream: atty::Stream) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && atty::is(stream),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice '{}'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RouteMode {
+    Nearest,
+    /// The nearest neighbour route, shortened by 2-opt
+    Optimized,
+}
+
+impl FromStr for RouteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(RouteMode::Nearest),
+            "optimized" => Ok(RouteMode::Optimized),
+            _ => Err(format!("Unknown route mode '{}'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    Json,
 }
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            _ => Err(format!("Unknown report format '{}'", s)),
+        }
+    }
+}
+
+/// The formats `treasure used-items` can list IDs in, which adds CSV to the [`ReportFormat`]s.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ItemListFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl FromStr for ItemListFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(ItemListFormat::Text),
+            "csv" => Ok(ItemListFormat::Csv),
+            "json" => Ok(ItemListFormat::Json),
+            _ => Err(format!("Unknown item list format '{}'", s)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Treasure {
+    /// List every item ID referenced by at least one treasure
+    UsedItems {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        /// Item data to include names alongside the IDs
+        #[structopt(short, long, parse(from_os_str))]
+        item_data: Option<PathBuf>,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, csv, json)
+        /// CSV writes an id,name header, then a row per ID with the name left empty when it isn't known
+        #[structopt(long, default_value = "text")]
+        format: ItemListFormat,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Write an item data file naming every item ID referenced by a treasure with a TODO placeholder
+    ScaffoldItems {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        /// Where to write the item data. An existing file is never overwritten
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Re-emit a snapshot written with --format bincode, given in place of the input directory
+    Load(DumpTreasure),
+    /// List the chests bound to each respawn slot, without dumping the zones
+    RespawnSlots {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Leave slots no chest is bound to out of the text report
+        #[structopt(long)]
+        hide_empty_slots: bool,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    #[cfg(feature = "tui_browse")]
+    /// Interactively browse groups, zones and chests, searching by item name
+    Browse {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// A JSON profile overriding game-version assumptions such as the item slot split percentages
+        #[structopt(long, parse(from_os_str))]
+        profile: Option<PathBuf>,
+        /// Decimal places for item chances
+        #[structopt(long, default_value = "2")]
+        precision: usize,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Print how a modded zone tree differs from a vanilla snapshot
+    DiffVanilla {
+        #[structopt(parse(from_os_str))]
+        /// A directory of modded zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        /// A snapshot of the unmodified game written with dump-treasure --format bincode
+        #[structopt(long, parse(from_os_str), env, default_value = "data/vanilla_treasures.bin")]
+        vanilla: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Find every chest with the given ID, across all zones
+    Find {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(long)]
+        id: u32,
+        /// Only search the zone with this name
+        #[structopt(long)]
+        zone: Option<String>,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// List the chests of a zone within a radius of a position, closest first
+    Near {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        /// Name of the zone to search
+        #[structopt(long)]
+        zone: String,
+        #[structopt(long, allow_hyphen_values = true)]
+        x: i16,
+        #[structopt(long, allow_hyphen_values = true)]
+        y: i16,
+        /// Greatest distance from the position to list chests at, in raw position units like --x and --y.
+        /// Profiles' position_scale isn't applied
+        #[structopt(long)]
+        radius: f64,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Decimal places for distances in text output. JSON keeps full precision
+        #[structopt(long, default_value = "2")]
+        precision: usize,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Plan a short route visiting each of a zone's chests once, and print the order and its length in raw position units
+    Route {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        /// Name of the zone to plan the route through
+        #[structopt(long)]
+        zone: String,
+        /// ID of the chest to start from. Defaults to the zone's first chest
+        #[structopt(long)]
+        start: Option<u32>,
+        /// Also draw the route on an SVG map of the zone, written to this path
+        #[structopt(long, parse(from_os_str))]
+        svg: Option<PathBuf>,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Decimal places for the route length in text output. JSON keeps full precision
+        #[structopt(long, default_value = "2")]
+        precision: usize,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Open a chest many times over with random rolls and print how often each outcome came up
+    Simulate {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        /// Name of the zone the chest is in
+        #[structopt(long)]
+        zone: String,
+        /// ID of the chest to simulate
+        #[structopt(long)]
+        id: u32,
+        /// How many respawn cycles to simulate
+        #[structopt(long, default_value = "10000")]
+        trials: u32,
+        /// Seed for the random rolls, to reproduce a run. A random seed is used and printed if not given
+        #[structopt(long)]
+        seed: Option<u64>,
+        /// Simulate with the Diamond Armlet equipped
+        #[structopt(long)]
+        diamond_armlet: bool,
+        /// A JSON profile overriding game-version assumptions such as the item slot split percentages
+        #[structopt(long, parse(from_os_str))]
+        profile: Option<PathBuf>,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Decimal places for percentages in text output. JSON keeps full precision
+        #[structopt(long, default_value = "2")]
+        precision: usize,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+    /// Hex dump the start of a zone script and list offsets that look like treasure tables,
+    /// for finding the offset and quantity of zones missing from the treasure data
+    Inspect {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// How many bytes from the start of the file to hex dump
+        #[structopt(long, default_value = "256")]
+        bytes: usize,
+        /// Fewest consecutive plausible records to report as a table
+        #[structopt(long, default_value = "3")]
+        min_records: usize,
+    },
+    /// Summarize chances, gil, respawn slots and item frequency across every zone
+    Stats {
+        #[structopt(parse(from_os_str))]
+        /// A directory of files containing zone scripts. Typically ps2data/plan_master/in/plan_map
+        input: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/treasure_data.json")]
+        treasure_data: PathBuf,
+        #[structopt(short, long, parse(from_os_str), env, default_value = "data/item_data.json")]
+        item_data: PathBuf,
+        /// The format of the item data. (json, gametext)
+        #[structopt(long, default_value = "json")]
+        item_format: ItemFormat,
+        /// How many of the most common items to list
+        #[structopt(long, default_value = "10")]
+        top: usize,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+        /// Skip the on-disk cache of parsed treasure and item data files
+        #[structopt(long)]
+        no_cache: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ItemData {
+    /// Report item IDs found in only one of two item data files, and IDs the two name differently
+    Diff {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+        /// (text, json)
+        #[structopt(long, default_value = "text")]
+        format: ReportFormat,
+    },
+}
+
 #[derive(StructOpt, Debug)]
 pub enum Vbf {
     /// Analyze the provided VBF
@@ -50,7 +820,10 @@ pub enum BattlePack {
         #[structopt(parse(from_os_str))]
         battle_pack: PathBuf,
         #[structopt(parse(from_os_str))]
-        output: Option<PathBuf>
+        output: Option<PathBuf>,
+        /// Only extract this section, or inclusive range of sections such as 3-7
+        #[structopt(long, parse(try_from_str = parse_section_range))]
+        sections: Option<(usize, usize)>,
     },
     /// Repack the directory created by unpack into a battle_pack.bin
     Repack {
@@ -58,11 +831,47 @@ pub enum BattlePack {
         input: PathBuf,
         #[structopt(parse(from_os_str))]
         battle_pack: PathBuf,
+        /// Re-read the written battle pack and check its sections match the inputs
+        #[structopt(long)]
+        validate: bool,
+        /// Deflate each section that gets smaller for it. Only this tool can read the result, not the game
+        #[structopt(long)]
+        compress: bool,
     },
     /// Modify the provided battle pack to allow all weapons to hit flying enemies
     AllowAllFlying {
         #[structopt(parse(from_os_str))]
         battle_pack: PathBuf,
+        /// Hex bytes locating the equipment section, used instead of the known signatures
+        #[structopt(long)]
+        signature: Option<String>,
+        /// Write the flags even if the first entry doesn't look like equipment
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Report how each equipment entry differs between two battle packs
+    DiffEquipment {
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+        /// Hex bytes locating the equipment section, used instead of the known signatures
+        #[structopt(long)]
+        signature: Option<String>,
+    },
+    /// Check the battle pack has the expected number of sections, exiting with 6 if it doesn't
+    AssertSections {
+        #[structopt(parse(from_os_str))]
+        battle_pack: PathBuf,
+        /// The exact number of sections the battle pack must have
+        #[structopt(long, required_unless_one = &["min", "max"], conflicts_with_all = &["min", "max"])]
+        expected: Option<usize>,
+        /// The fewest sections the battle pack may have
+        #[structopt(long)]
+        min: Option<usize>,
+        /// The most sections the battle pack may have
+        #[structopt(long)]
+        max: Option<usize>,
     },
     #[cfg(feature = "battle_fuse")]
     /// Create a FUSE of the battle_pack, in the same format as unpack
@@ -0,0 +1,9 @@
+//! The stable API for using this crate as a library, for `use ff12tza_utils::prelude::*;`.
+
+pub use crate::battle_pack::{BattlePackReader, BattlePackWriter};
+pub use crate::error::TreasureError;
+pub use crate::opt::{MarkerShape, RouteMode};
+pub use crate::treasure::{Bounds, ItemData, ParsedZone, TreasureData, ZoneData, ZoneTreasure};
+pub use crate::treasure::{parse_record, parse_zones, read_treasure_files, RECORD_SIZE};
+pub use crate::treasure::{geojson_feature, json_treasure, write_canonical_json, write_geojson, write_json, write_lua};
+pub use crate::treasure::{render_svg, PlotError, PlotOptions};
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::execute;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
+use tui::Terminal;
+use tui::backend::{Backend, CrosstermBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Modifier, Style};
+use tui::terminal::Frame;
+use tui::widgets::{Block, Borders, List, ListState, Paragraph, Text};
+
+use crate::opt::ItemFormat;
+use crate::treasure::{get_datas, get_profile, parse_zones, ItemData, TreasureData, ZoneTreasure};
+use crate::treasure::profile::DropSplit;
+
+struct Chest {
+    treasure: ZoneTreasure,
+    names: [String; 4],
+}
+
+struct Zone {
+    name: String,
+    chests: Vec<Chest>,
+}
+
+struct Group {
+    name: String,
+    zones: Vec<Zone>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Focus {
+    Groups = 0,
+    Zones = 1,
+    Chests = 2,
+}
+
+struct Browser {
+    groups: Vec<Group>,
+    split: DropSplit,
+    precision: usize,
+    focus: Focus,
+    /// Selected index within the visible groups, zones and chests, in that order
+    selected: [usize; 3],
+    search: String,
+    searching: bool,
+}
+
+pub fn browse(input: PathBuf, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, profile: Option<PathBuf>, precision: usize, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+    let split = get_profile(profile).split;
+    let groups = load_groups(input, &treasure_data, &item_data);
+    if groups.is_empty() {
+        eprintln!("No zones with treasure data were found to browse.");
+        std::process::exit(4);
+    }
+
+    let mut browser = Browser { groups, split, precision, focus: Focus::Groups, selected: [0; 3], search: String::new(), searching: false };
+    if let Err(err) = run(&mut browser) {
+        eprintln!("Error occurred while browsing. Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn load_groups(input: PathBuf, treasure_data: &TreasureData, item_data: &ItemData) -> Vec<Group> {
+    let mut groups: BTreeMap<String, Vec<Zone>> = BTreeMap::new();
+    for parsed in parse_zones(input, treasure_data) {
+        let chests = parsed.treasures.into_iter().map(|treasure| {
+            let items = treasure.all_items();
+            let name = |id: u16| match item_data.name(id) {
+                Some(name) if name.is_empty() => String::from("(none)"),
+                Some(name) => name.clone(),
+                None => format!("Unknown 0x{:04x}", id),
+            };
+            Chest { treasure, names: [name(items[0]), name(items[1]), name(items[2]), name(items[3])] }
+        }).collect();
+        groups.entry(parsed.group.to_string()).or_default().push(Zone { name: parsed.zone.name.clone(), chests });
+    }
+    groups.into_iter().map(|(name, mut zones)| {
+        zones.sort_by(|a, b| a.name.cmp(&b.name));
+        Group { name, zones }
+    }).collect()
+}
+
+fn run(browser: &mut Browser) -> Result<(), Box<dyn Error>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let res = event_loop(&mut terminal, browser);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    res
+}
+
+fn event_loop<B: Backend>(terminal: &mut Terminal<B>, browser: &mut Browser) -> Result<(), Box<dyn Error>> {
+    loop {
+        terminal.draw(|mut f| browser.draw(&mut f))?;
+        if let Event::Key(key) = event::read()? {
+            if !browser.key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Browser {
+    fn matches(&self, chest: &Chest) -> bool {
+        if self.search.is_empty() { return true; }
+        let query = self.search.to_lowercase();
+        chest.names.iter().any(|name| name.to_lowercase().contains(&query))
+    }
+
+    fn zone_visible(&self, zone: &Zone) -> bool {
+        zone.chests.iter().any(|chest| self.matches(chest))
+    }
+
+    /// The visible groups, then the zones of the selected group and the chests of the selected zone.
+    fn selection(&self) -> (Vec<&Group>, Vec<&Zone>, Vec<&Chest>) {
+        let groups = self.groups.iter()
+            .filter(|group| group.zones.iter().any(|zone| self.zone_visible(zone)))
+            .collect::<Vec<_>>();
+        let zones = match groups.get(self.selected[0]) {
+            Some(group) => group.zones.iter().filter(|zone| self.zone_visible(zone)).collect(),
+            None => Vec::new(),
+        };
+        let chests = match zones.get(self.selected[1]) {
+            Some(zone) => zone.chests.iter().filter(|chest| self.matches(chest)).collect(),
+            None => Vec::new(),
+        };
+        (groups, zones, chests)
+    }
+
+    /// Handles a key press, returning false once the browser should close.
+    fn key(&mut self, code: KeyCode) -> bool {
+        if self.searching {
+            match code {
+                KeyCode::Char(c) => { self.search.push(c); self.selected = [0; 3]; },
+                KeyCode::Backspace => { self.search.pop(); self.selected = [0; 3]; },
+                KeyCode::Esc => { self.search.clear(); self.searching = false; self.selected = [0; 3]; },
+                KeyCode::Enter => self.searching = false,
+                _ => {}
+            }
+            return true;
+        }
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Left => self.focus = match self.focus { Focus::Chests => Focus::Zones, _ => Focus::Groups },
+            KeyCode::Right | KeyCode::Tab => self.focus = match self.focus { Focus::Groups => Focus::Zones, _ => Focus::Chests },
+            KeyCode::Up => self.move_selection(-1),
+            KeyCode::Down => self.move_selection(1),
+            _ => {}
+        }
+        true
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = {
+            let (groups, zones, chests) = self.selection();
+            match self.focus { Focus::Groups => groups.len(), Focus::Zones => zones.len(), Focus::Chests => chests.len() }
+        };
+        if len == 0 { return; }
+        let level = self.focus as usize;
+        let current = self.selected[level] as isize;
+        self.selected[level] = std::cmp::max(0, std::cmp::min(len as isize - 1, current + delta)) as usize;
+        // Lists below the moved one now show something else, so start them from the top.
+        for lower in self.selected[level + 1..].iter_mut() {
+            *lower = 0;
+        }
+    }
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(20), Constraint::Percentage(25), Constraint::Percentage(20), Constraint::Percentage(35)].as_ref())
+            .split(rows[1]);
+
+        let title = if self.searching { "Search items (Enter to keep, Esc to clear)" } else { "Search items (/ to edit, arrows to move, q to quit)" };
+        let search = [Text::raw(self.search.as_str())];
+        f.render_widget(Paragraph::new(search.iter()).block(Block::default().borders(Borders::ALL).title(title)), rows[0]);
+
+        let (groups, zones, chests) = self.selection();
+        self.draw_list(f, columns[0], "Groups", Focus::Groups, groups.iter().map(|group| group.name.clone()).collect());
+        self.draw_list(f, columns[1], "Zones", Focus::Zones, zones.iter().map(|zone| zone.name.clone()).collect());
+        self.draw_list(f, columns[2], "Chests", Focus::Chests, chests.iter().map(|chest| format!("{:<3} {}", chest.treasure.id, chest.names[0])).collect());
+
+        let details = match chests.get(self.selected[2]) { Some(chest) => self.details(chest), None => Vec::new() };
+        f.render_widget(Paragraph::new(details.iter()).block(Block::default().borders(Borders::ALL).title("Chest")).wrap(true), columns[3]);
+    }
+
+    fn draw_list<B: Backend>(&self, f: &mut Frame<B>, area: Rect, title: &str, focus: Focus, items: Vec<String>) {
+        let mut state = ListState::default();
+        if !items.is_empty() {
+            state.select(Some(self.selected[focus as usize]));
+        }
+        let title_style = if self.focus == focus { Style::default().modifier(Modifier::BOLD) } else { Style::default() };
+        let list = List::new(items.into_iter().map(Text::raw))
+            .block(Block::default().borders(Borders::ALL).title(title).title_style(title_style))
+            .highlight_style(Style::default().modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        f.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Describes the chest, with each item's chance given that the chest has spawned.
+    fn details(&self, chest: &Chest) -> Vec<Text<'static>> {
+        let t = &chest.treasure;
+        let p = self.precision;
+        let item_chance = |split: u8| (100 - std::cmp::min(t.gil_chance, 100)) as f64 * split as f64 / 100.0;
+        let respawn = if t.respawn_slot == 255 { String::from("none (respawns)") } else { format!("{:02x}", t.respawn_slot) };
+        vec![
+            Text::raw(format!("ID: {}\n", t.id)),
+            Text::raw(format!("Position: ({}, {})\n", t.pos_x, t.pos_y)),
+            Text::raw(format!("Respawn slot: {}\n", respawn)),
+            Text::raw(format!("Spawn chance: {}%\n", t.spawn_chance)),
+            Text::raw(format!("Gil chance: {}%\n", t.gil_chance)),
+            Text::raw(format!("Gil: {} (Diamond Armlet: {})\n", t.gil_amount, t.rare_gil_amount)),
+            Text::raw("\nItems\n"),
+            Text::raw(format!("  {} ({:.*}%)\n", chest.names[0], p, item_chance(self.split.first_item))),
+            Text::raw(format!("  {} ({:.*}%)\n", chest.names[1], p, item_chance(self.split.second_item))),
+            Text::raw("\nItems with Diamond Armlet\n"),
+            Text::raw(format!("  {} ({:.*}%)\n", chest.names[2], p, item_chance(self.split.rare_first_item))),
+            Text::raw(format!("  {} ({:.*}%)\n", chest.names[3], p, item_chance(self.split.rare_second_item))),
+        ]
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+
+/// Bumped whenever the layout of cached data changes, so older entries are ignored.
+const CACHE_FORMAT: u32 = 5;
+
+/// Identifies the exact version of a data file a cache entry was built from.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CacheKey {
+    format: u32,
+    path: PathBuf,
+    modified_secs: u64,
+    modified_nanos: u32,
+    size: u64,
+}
+
+impl CacheKey {
+    fn of(source: &Path) -> Option<CacheKey> {
+        let path = source.canonicalize().ok()?;
+        let meta = std::fs::metadata(&path).ok()?;
+        let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(CacheKey { format: CACHE_FORMAT, path, modified_secs: modified.as_secs(), modified_nanos: modified.subsec_nanos(), size: meta.len() })
+    }
+}
+
+fn cache_path(name: &str, key: &CacheKey) -> Option<PathBuf> {
+    let mut hasher = DefaultHasher::new();
+    key.path.hash(&mut hasher);
+    dirs::cache_dir().map(|dir| dir.join("ff12tza-utils").join(format!("{}-{:016x}.bin", name, hasher.finish())))
+}
+
+/// Loads the cached copy of `source`, if one exists and the source hasn't changed since.
+pub fn load<T: DeserializeOwned>(name: &str, source: &Path) -> Option<T> {
+    let key = CacheKey::of(source)?;
+    let file = File::open(cache_path(name, &key)?).ok()?;
+    let mut reader = BufReader::new(file);
+    let cached_key: CacheKey = bincode::deserialize_from(&mut reader).ok()?;
+    if cached_key != key {
+        return None;
+    }
+    bincode::deserialize_from(&mut reader).ok()
+}
+
+pub fn store<T: Serialize>(name: &str, source: &Path, data: &T) -> Result<(), Box<dyn std::error::Error>> {
+    let key = CacheKey::of(source).ok_or("unable to read data file metadata")?;
+    let path = cache_path(name, &key).ok_or("no cache directory available")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    bincode::serialize_into(&mut writer, &key)?;
+    bincode::serialize_into(&mut writer, data)?;
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::opt::{ItemFormat, ReportFormat};
+use crate::treasure::{get_datas, parse_zones, FromDataPath, ItemData, ZoneTreasure};
+use crate::treasure::dump::read_snapshot;
+
+pub fn diff_vanilla(input: PathBuf, vanilla: PathBuf, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, no_cache: bool) {
+    if !vanilla.exists() {
+        eprintln!("Missing vanilla snapshot {:?}.", vanilla);
+        eprintln!("Create one from an unmodified extract with dump-treasure --format bincode, then use the --vanilla option.");
+        std::process::exit(2);
+    }
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let vanilla: BTreeMap<String, Vec<ZoneTreasure>> = read_snapshot(&vanilla).into_iter()
+        .map(|(_, name, treasures)| (name, treasures))
+        .collect();
+    let modded: BTreeMap<String, Vec<ZoneTreasure>> = parse_zones(input, &treasure_data).into_iter()
+        .map(|parsed| (parsed.zone.name.clone(), parsed.treasures))
+        .collect();
+
+    let mut changed_zones = 0;
+    for (name, modded_treasures) in &modded {
+        let lines = match vanilla.get(name) {
+            Some(vanilla_treasures) => zone_changes(vanilla_treasures, modded_treasures, &item_data),
+            None => vec![String::from("zone not in vanilla snapshot")],
+        };
+        if !lines.is_empty() {
+            changed_zones += 1;
+            println!("{}", name);
+            for line in lines {
+                println!("\t{}", line);
+            }
+        }
+    }
+    for name in vanilla.keys().filter(|name| !modded.contains_key(*name)) {
+        changed_zones += 1;
+        println!("{}", name);
+        println!("\tzone missing from the modded tree");
+    }
+    eprintln!("{} zones differ from vanilla.", changed_zones);
+}
+
+/// Describes each chest that was added, removed or changed, matching chests by ID.
+fn zone_changes(vanilla: &[ZoneTreasure], modded: &[ZoneTreasure], item_data: &ItemData) -> Vec<String> {
+    let vanilla = vanilla.iter().map(|t| (t.id, t)).collect::<BTreeMap<_, _>>();
+    let modded = modded.iter().map(|t| (t.id, t)).collect::<BTreeMap<_, _>>();
+
+    let mut lines = Vec::new();
+    for (id, after) in &modded {
+        match vanilla.get(id) {
+            Some(before) => {
+                for (field, old, new) in chest_changes(before, after, item_data) {
+                    lines.push(format!("{}: {} {} -> {}", id, field, old, new));
+                }
+            },
+            None => lines.push(format!("{}: added", id)),
+        }
+    }
+    for id in vanilla.keys().filter(|id| !modded.contains_key(*id)) {
+        lines.push(format!("{}: removed", id));
+    }
+    lines
+}
+
+fn chest_changes(before: &ZoneTreasure, after: &ZoneTreasure, item_data: &ItemData) -> Vec<(&'static str, String, String)> {
+    let item = |id: u16| match item_data.name(id) {
+        Some(name) => name.clone(),
+        None => format!("Unknown 0x{:04x}", id),
+    };
+    let mut changes = Vec::new();
+    let mut compare = |field: &'static str, old: String, new: String| if old != new { changes.push((field, old, new)); };
+    compare("position", format!("({}, {})", before.pos_x, before.pos_y), format!("({}, {})", after.pos_x, after.pos_y));
+    compare("respawn_slot", format!("{:x}", before.respawn_slot), format!("{:x}", after.respawn_slot));
+    compare("spawn_chance", before.spawn_chance.to_string(), after.spawn_chance.to_string());
+    compare("gil_chance", before.gil_chance.to_string(), after.gil_chance.to_string());
+    compare("gil_amount", before.gil_amount.to_string(), after.gil_amount.to_string());
+    compare("rare_gil_amount", before.rare_gil_amount.to_string(), after.rare_gil_amount.to_string());
+    compare("first_item", item(before.first_item), item(after.first_item));
+    compare("second_item", item(before.second_item), item(after.second_item));
+    compare("rare_first_item", item(before.rare_first_item), item(after.rare_first_item));
+    compare("rare_second_item", item(before.rare_second_item), item(after.rare_second_item));
+    changes
+}
+
+#[derive(Serialize, Default, Debug)]
+struct ItemDataDiff<'a> {
+    only_in_a: Vec<NamedItem<'a>>,
+    only_in_b: Vec<NamedItem<'a>>,
+    renamed: Vec<RenamedItem<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+struct NamedItem<'a> {
+    id: u32,
+    name: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct RenamedItem<'a> {
+    id: u32,
+    a: &'a str,
+    b: &'a str,
+}
+
+/// Prints the item IDs only one of the two item data files has, and the IDs they name differently.
+pub fn diff_item_data(a: PathBuf, b: PathBuf, format: ReportFormat) {
+    let (a_data, b_data) = (open_item_data(&a), open_item_data(&b));
+    let a_ids = a_data.ids.iter().map(|(id, name)| (*id, name.as_str())).collect::<BTreeMap<_, _>>();
+    let b_ids = b_data.ids.iter().map(|(id, name)| (*id, name.as_str())).collect::<BTreeMap<_, _>>();
+
+    let mut diff = ItemDataDiff::default();
+    for (&id, &name) in &a_ids {
+        match b_ids.get(&id) {
+            Some(&b_name) if b_name != name => diff.renamed.push(RenamedItem { id, a: name, b: b_name }),
+            Some(_) => {},
+            None => diff.only_in_a.push(NamedItem { id, name }),
+        }
+    }
+    for (&id, &name) in b_ids.iter().filter(|(id, _)| !a_ids.contains_key(*id)) {
+        diff.only_in_b.push(NamedItem { id, name });
+    }
+
+    match format {
+        ReportFormat::Text => {
+            println!("Only in {:?}:", a);
+            for item in &diff.only_in_a {
+                println!("\t{}\t{}", item.id, item.name);
+            }
+            println!("Only in {:?}:", b);
+            for item in &diff.only_in_b {
+                println!("\t{}\t{}", item.id, item.name);
+            }
+            println!("Named differently:");
+            for item in &diff.renamed {
+                println!("\t{}\t{:?} -> {:?}", item.id, item.a, item.b);
+            }
+        },
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(std::io::stdout(), &diff).expect("Writing to stdout");
+            println!();
+        },
+    }
+    eprintln!("{} only in the first, {} only in the second, {} named differently.", diff.only_in_a.len(), diff.only_in_b.len(), diff.renamed.len());
+}
+
+fn open_item_data(path: &PathBuf) -> ItemData {
+    if !path.exists() {
+        eprintln!("Missing item data file {:?}.", path);
+        std::process::exit(2);
+    }
+    match ItemData::open(path.clone()) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Error occurred while reading the item data file {:?}.", path);
+            eprintln!("Error: {}", err);
+            std::process::exit(3);
+        }
+    }
+}
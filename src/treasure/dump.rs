@@ -0,0 +1,1214 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::io::Result as IOResult;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use zip::write::{FileOptions, ZipWriter};
+
+use crate::error::TreasureError;
+use crate::opt::{DumpTreasure, OutputFormat, SortBy};
+use crate::treasure::{data_paths, get_data, get_item_data, get_profile, glob_files, group_of, listed_files, normalized_positions, orphaned_group_entries, parse_record, parse_zone_files, read_raw_records, record_hex, zone_files};
+use crate::treasure::{Bounds, ItemData, LineEndings, OutputWriter, TreasureData, ZoneData, ZoneTreasure, RECORD_SIZE};
+use crate::treasure::filter;
+use crate::treasure::output;
+use crate::treasure::plotter::{self, PlotOptions, RegionZone};
+use crate::treasure::profile::{DropSplit, Profile};
+use crate::treasure::slots::SlotBinds;
+#[cfg(feature = "sqlite")]
+use crate::treasure::sqlite;
+use crate::treasure::warnings::{WarningKind, Warnings};
+use crate::treasure::watch;
+use crate::utils;
+
+/// Every dumped zone as `(group, zone name, treasures)`. This is what `--format bincode` writes.
+pub type Snapshot = Vec<(String, String, Vec<ZoneTreasure>)>;
+
+const SNAPSHOT_FILE: &str = "treasures.bin";
+
+/// A treasure with its four item names resolved.
+pub(super) type Row = (ZoneTreasure, [String; 4]);
+
+pub fn dump_treasure(opts: DumpTreasure) {
+    let mut opts = opts;
+    if opts.input_file.is_some() {
+        // There's no input directory, so a lone path is where the output goes
+        match (opts.input.take(), opts.output.is_some() || opts.output_option.is_some()) {
+            (Some(path), false) => opts.output = Some(path),
+            (Some(_), true) => {
+                eprintln!("--input-file replaces the input directory, so only an output can be given alongside it.");
+                std::process::exit(4);
+            },
+            (None, _) => {},
+        }
+    }
+    if let Some(path) = opts.output_option.take() {
+        opts.output = Some(path);
+    }
+    if opts.output.is_none() {
+        for (given, flag) in &[(opts.create_maps, "--create-maps"), (opts.merge_maps, "--merge-maps"), (opts.raw_sidecar, "--raw-sidecar")] {
+            if *given {
+                eprintln!("{} writes beside the output, so an output must be given.", flag);
+                std::process::exit(4);
+            }
+        }
+    }
+    if opts.watch {
+        return watch::watch(&opts);
+    }
+    let (treasure_path, item_path) = data_paths(&opts);
+    let mut treasure_data: TreasureData = get_data(treasure_path, "treasure", "TREASURE", !opts.no_cache);
+    let item_data = get_item_data(item_path, opts.item_format, !opts.no_cache);
+    if let Some(language) = item_data.language.as_ref() {
+        eprintln!("Using item names in language '{}'.", language);
+    }
+    if item_data.has_wide_ids() {
+        eprintln!("The item data has IDs above 0x{:x}. Treasure records only hold 16 bit IDs, so those items can't appear.", u16::MAX);
+    }
+    if let Some(file) = opts.input_file.as_ref() {
+        single_zone(&mut treasure_data, &opts, file);
+    }
+    let treasure_data = treasure_data;
+    let files = match opts.files_from.as_ref() {
+        Some(list) => listed_files(list),
+        None if opts.input_file.is_some() => vec![opts.input().clone()],
+        None if opts.input().as_path() == Path::new("-") => listed_files(opts.input()),
+        None => {
+            // Walked files are dumped group by group, then by file stem, so repeated runs write identical output.
+            let mut files = match opts.input_glob.as_ref() {
+                Some(pattern) => glob_files(opts.input(), pattern),
+                None => zone_files(opts.input().clone()).collect::<Vec<_>>(),
+            };
+            files.sort_by_cached_key(|path| {
+                let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                (group_of(&treasure_data, &stem, &opts.unknown_group).to_string(), stem)
+            });
+            files
+        },
+    };
+    if opts.dry_run {
+        return print_plan(&opts, &treasure_data, &files);
+    }
+    let mut dump = Dump::new(&opts, &item_data);
+    let orphans = orphaned_group_entries(&treasure_data);
+    if !orphans.is_empty() {
+        let list = orphans.iter().map(|(group, stem)| format!("{}/{}", group, stem)).collect::<Vec<_>>().join(", ");
+        dump.warnings.warn(WarningKind::OrphanedGroupEntry, None, None, format!("{} group entries have no zone data and won't be dumped: {}", orphans.len(), list));
+    }
+    if opts.exclusive_loot {
+        let zones = parse_zone_files(files.iter().cloned(), &treasure_data);
+        dump.exclusive = exclusive_loot(zones.iter().map(|zone| (zone.group, zone.treasures.as_slice())));
+    }
+
+    utils::defer_interrupts();
+    let mut found_files = false;
+    for path in files {
+        found_files = true;
+        if dump.limit_reached() { break; }
+        if utils::interrupted() {
+            eprintln!("Interrupted. Stopping after the last completed zone.");
+            break;
+        }
+        let file_stem = path.file_stem().unwrap().to_str().unwrap().to_owned();
+
+        let group = group_of(&treasure_data, &file_stem, &opts.unknown_group);
+        if dump.excluded(group, &file_stem) { continue; }
+
+        let zone = match treasure_data.zones.get(&file_stem) {
+            Some(zone) if dump.excluded(group, &zone.name) => continue,
+            Some(zone) => zone,
+            None => {
+                dump.warnings.record(WarningKind::MissingZone, Some(&file_stem), None, format!("No zone data for {:?}", path));
+                continue;
+            }
+        };
+
+        let started = Instant::now();
+        let res = File::open(path.as_path()).map_err(|e| TreasureError::from(e))
+            .and_then(|file| read_raw_records(file, &zone))
+            .and_then(|records| Ok((records.iter().map(parse_record).collect::<Result<Vec<_>, _>>()?, records)));
+        let parse_time = started.elapsed();
+
+        match res {
+            Ok((zone_treasures, records)) => {
+                if let Some(bounds) = zone.bounds {
+                    for treasure in zone_treasures.iter().filter(|t| !bounds.contains(t)) {
+                        dump.warnings.warn(WarningKind::OutOfBounds, Some(&zone.name), Some(treasure.id),
+                                           format!("Position ({}, {}) is outside the zone bounds", treasure.pos_x, treasure.pos_y));
+                    }
+                }
+                if opts.merge_maps {
+                    dump.add_to_region(group, zone, &zone_treasures);
+                }
+                dump.zone(group, &zone.name, zone.bounds, zone_treasures, parse_time);
+                if opts.dump_raw_bytes {
+                    dump.raw_records(group, &zone.name, &records);
+                }
+                if opts.raw_sidecar {
+                    dump.raw_sidecar(group, &zone.name, &records);
+                }
+            },
+            Err(err) => {
+                eprintln!("An error occurred while processing file {:?}. Error: {}", path.as_path(), err);
+            }
+        }
+    }
+    if !found_files {
+        eprintln!("Warning: No .ebp files found under {:?}. Is this the right input directory?", opts.input());
+        if opts.strict {
+            std::process::exit(4);
+        }
+    }
+    dump.finish();
+}
+
+/// Adds the `--input-file` zone to the treasure data under the file's own stem, so it's dumped like any other.
+/// Its offset and quantity come from `--table-offset` and `--quantity`, or else the zone named by `--zone`,
+/// whose group it joins.
+fn single_zone(treasure_data: &mut TreasureData, opts: &DumpTreasure, file: &Path) {
+    let name = opts.zone.as_ref().expect("--input-file requires --zone");
+    let stem = file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let known = treasure_data.zones.iter()
+        .find(|(_, zone)| zone.name == *name)
+        .map(|(known_stem, zone)| (known_stem.clone(), zone.offset, zone.quantity, zone.bounds, zone.world_offset));
+    let offset = opts.table_offset.or_else(|| known.as_ref().map(|known| known.1));
+    let quantity = opts.quantity.or_else(|| known.as_ref().map(|known| known.2));
+    let (offset, quantity) = match (offset, quantity) {
+        (Some(offset), Some(quantity)) => (offset, quantity),
+        _ => {
+            eprintln!("No zone named {:?} is in the treasure data. Give its --table-offset and --quantity instead.", name);
+            std::process::exit(4);
+        }
+    };
+    if let Some((known_stem, ..)) = known.as_ref() {
+        for stems in treasure_data.groups.values_mut().filter(|stems| stems.contains(known_stem)) {
+            stems.insert(stem.clone());
+        }
+    }
+    let (bounds, world_offset) = known.map(|known| (known.3, known.4)).unwrap_or((None, None));
+    treasure_data.zones.insert(stem, ZoneData { name: name.clone(), offset, quantity, bounds, world_offset });
+}
+
+/// Prints each zone the dump would write, where to and with how many treasures, without reading the
+/// zone scripts or writing anything.
+fn print_plan(opts: &DumpTreasure, treasure_data: &TreasureData, files: &[PathBuf]) {
+    let mut zones = 0;
+    let mut treasures = 0;
+    for path in files {
+        if opts.limit.map(|limit| zones >= limit).unwrap_or(false) { break; }
+        let file_stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(stem) => stem,
+            None => continue,
+        };
+        let group = group_of(treasure_data, file_stem, &opts.unknown_group);
+        let zone = match treasure_data.zones.get(file_stem) {
+            Some(zone) => zone,
+            None => {
+                println!("{:?}: no zone data, skipped", path);
+                continue;
+            }
+        };
+        let excluded = opts.exclude_group.iter().any(|g| g == group)
+            || opts.exclude_zone.iter().any(|z| z == file_stem || z == &zone.name);
+        if excluded {
+            println!("{}: {} excluded", group, zone.name);
+            continue;
+        }
+        let destination = match opts.output.as_ref() {
+            Some(output) if opts.is_database() => format!("{:?}", output),
+            Some(dir) if opts.has_format(OutputFormat::Bincode) => format!("{:?}", dir.join(SNAPSHOT_FILE)),
+            Some(dir) => opts.format.iter().map(|format| format!("{:?}", zone_path(dir, group, &zone.name).with_extension(format.extension()))).collect::<Vec<_>>().join(", "),
+            None => String::from("stdout"),
+        };
+        println!("{}: {} -> {} ({} treasures)", group, zone.name, destination, zone.quantity);
+        zones += 1;
+        treasures += zone.quantity as usize;
+    }
+    println!("Would dump {} zones holding {} treasures. Nothing was written.", zones, treasures);
+}
+
+/// Reads a snapshot written by `--format bincode`, exiting if it can't be read.
+pub(super) fn read_snapshot(path: &PathBuf) -> Snapshot {
+    let snapshot: Result<Snapshot, TreasureError> = File::open(path).map_err(TreasureError::from)
+        .and_then(|file| Ok(bincode::deserialize_from(BufReader::new(file))?));
+    match snapshot {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            eprintln!("Unable to read snapshot {:?}. Error: {}", path, err);
+            std::process::exit(3);
+        }
+    }
+}
+
+/// Maps each item to the group holding every chest it drops from, for items found in only one group.
+fn exclusive_loot<'a, I: Iterator<Item = (&'a str, &'a [ZoneTreasure])>>(zones: I) -> HashMap<u16, String> {
+    let mut sources: HashMap<u16, HashSet<&str>> = HashMap::new();
+    for (group, treasures) in zones {
+        for treasure in treasures {
+            for id in treasure.all_items().iter() {
+                sources.entry(*id).or_default().insert(group);
+            }
+        }
+    }
+    sources.into_iter()
+        .filter(|(_, groups)| groups.len() == 1)
+        .map(|(id, groups)| (id, groups.into_iter().next().unwrap().to_string()))
+        .collect()
+}
+
+/// Re-emits a snapshot written by `--format bincode`, which is given in place of the input directory.
+pub fn load_treasure(opts: DumpTreasure) {
+    let (_, item_path) = data_paths(&opts);
+    let item_data = get_item_data(item_path, opts.item_format, !opts.no_cache);
+    let snapshot = read_snapshot(opts.input());
+    let mut dump = Dump::new(&opts, &item_data);
+    if opts.exclusive_loot {
+        dump.exclusive = exclusive_loot(snapshot.iter().map(|(group, _, treasures)| (group.as_str(), treasures.as_slice())));
+    }
+    for (group, name, treasures) in snapshot {
+        if dump.limit_reached() { break; }
+        if dump.excluded(&group, &name) { continue; }
+        dump.zone(&group, &name, None, treasures, Duration::default());
+    }
+    dump.finish();
+}
+
+/// Where the time went for one zone, recorded with `--timing`.
+struct ZoneTiming {
+    zone: String,
+    parse: Duration,
+    render: Duration,
+}
+
+/// The number of zones listed by `--timing`, slowest first.
+const TIMING_ROWS: usize = 10;
+
+/// Output state shared across every zone of a single dump.
+pub(super) struct Dump<'a> {
+    opts: &'a DumpTreasure,
+    item_data: &'a ItemData,
+    profile: Profile,
+    /// The profile's position scale, or --position-scale
+    position_scale: f64,
+    plot_opts: PlotOptions,
+    /// The output directory, or the root of the archive when writing to a zip
+    output: Option<PathBuf>,
+    archive: Option<ZipWriter<File>>,
+    slot_binds: SlotBinds,
+    /// Filled in before the first zone when `--exclusive-loot` is set. See [`exclusive_loot`].
+    exclusive: HashMap<u16, String>,
+    zones: usize,
+    excluded_zones: usize,
+    /// Group of the last zone written with `--format checklist`
+    last_group: Option<String>,
+    html_maps: Vec<(String, String, String)>,
+    /// Zones to draw on each group's merged map, when `--merge-maps` is set
+    regions: BTreeMap<String, Vec<RegionZone>>,
+    snapshot: Snapshot,
+    warnings: Warnings,
+    timings: Vec<ZoneTiming>,
+}
+
+impl<'a> Dump<'a> {
+    pub(super) fn new(opts: &'a DumpTreasure, item_data: &'a ItemData) -> Dump<'a> {
+        assert!(!opts.create_maps || opts.output.is_some());
+        let profile = get_profile(opts.profile.clone());
+        let position_scale = opts.position_scale.unwrap_or(profile.position_scale);
+        if !(position_scale.is_finite() && position_scale > 0.0) {
+            eprintln!("The position scale must be a positive number, not {}.", position_scale);
+            std::process::exit(4);
+        }
+        // --map-region is given in world units, so it's compared against raw positions scaled back up
+        let raw = |pos: i16| (pos as f64 * position_scale).round() as i16;
+        let plot_opts = PlotOptions {
+            flip_y: opts.flip_y,
+            scale: opts.scale / position_scale,
+            offset: opts.offset,
+            relative: opts.relative_positions,
+            marker: opts.marker,
+            marker_size: opts.marker_size,
+            highlight: opts.highlight.clone().unwrap_or_default(),
+            route: opts.route,
+            shared_badges: opts.warn_shared_positions,
+            map_region: opts.map_region.map(|(x0, y0, x1, y1)| Bounds {
+                min_x: raw(std::cmp::min(x0, x1)),
+                min_y: raw(std::cmp::min(y0, y1)),
+                max_x: raw(std::cmp::max(x0, x1)),
+                max_y: raw(std::cmp::max(y0, y1)),
+            }),
+        };
+
+        let per_zone = opts.format.iter().all(|format| *format != OutputFormat::Bincode && !format.is_database());
+        if opts.format.len() > 1 && !per_zone {
+            eprintln!("--format bincode and sqlite write the whole dump to one file, so they can't be combined with other formats.");
+            std::process::exit(4);
+        }
+        let extensions = opts.format.iter().map(|format| format.extension()).collect::<HashSet<_>>();
+        if extensions.len() < opts.format.len() {
+            eprintln!("Each --format needs its own file extension. JSON and canonical JSON would both write .json files.");
+            std::process::exit(4);
+        }
+
+        if opts.is_database() && (opts.output.is_none() || opts.create_maps) {
+            eprintln!("--format sqlite needs an output naming the database file, and can't be used with --create-maps.");
+            std::process::exit(4);
+        }
+
+        if opts.append && !opts.format.iter().all(|format| *format == OutputFormat::Text || *format == OutputFormat::Json || *format == OutputFormat::CanonicalJson) {
+            eprintln!("--append only works with text and JSON output.");
+            std::process::exit(4);
+        }
+
+        if opts.loot_set && !per_zone {
+            eprintln!("--loot-set only works with text and JSON output.");
+            std::process::exit(4);
+        }
+
+        if opts.rare_focus && !per_zone {
+            eprintln!("--rare-focus only works with text, JSON and GeoJSON output.");
+            std::process::exit(4);
+        }
+
+        let archive_path = opts.output.as_ref()
+            .filter(|path| !opts.is_database() && path.extension().map(|ext| ext.eq_ignore_ascii_case("zip")).unwrap_or(false));
+        if archive_path.is_some() && opts.append {
+            eprintln!("--append can't add to a zip archive output.");
+            std::process::exit(4);
+        }
+
+        let input = opts.input();
+        let listed = opts.files_from.is_some() || input == Path::new("-");
+        if !listed && !input.exists() {
+            eprintln!("Non-existent input: {:?}", input);
+            std::process::exit(4);
+        }
+        let mut archive = None;
+        let output = if opts.is_database() {
+            // The database is written when the dump finishes; there's no directory of zone files.
+            None
+        } else if let Some(path) = archive_path {
+            match File::create(path) {
+                Ok(file) => archive = Some(ZipWriter::new(file)),
+                Err(err) => {
+                    eprintln!("Unable to create the archive {:?}. Error: {}", path, err);
+                    std::process::exit(4);
+                }
+            }
+            // Files are laid out inside the archive as they would be in an output directory
+            Some(PathBuf::new())
+        } else if !opts.output.as_ref().map(|dir| dir.exists()).unwrap_or(true) {
+            let dir = opts.output.clone().unwrap();
+            println!("Non-existent output directory: {:?}. Creating...", &dir);
+            if let Err(err) = std::fs::create_dir(&dir) {
+                eprintln!("Unable to create output directory. Error: {}", err);
+                std::process::exit(4);
+            }
+            Some(dir)
+        } else { opts.output.clone() };
+
+        if let Some(dir) = output.as_ref().filter(|_| archive.is_none()) {
+            if let Err(err) = check_writable(dir) {
+                eprintln!("Unable to write to the output directory {:?}. Error: {}", dir, err);
+                eprintln!("Check its permissions, or choose another output directory.");
+                std::process::exit(4);
+            }
+            if let (Ok(out_dir), Ok(in_dir)) = (dir.canonicalize(), input.canonicalize()) {
+                if out_dir.starts_with(&in_dir) {
+                    eprintln!("The output directory {:?} is inside the input directory {:?}.", dir, input);
+                    eprintln!("Generated files would be scanned on later runs. Choose an output directory outside the input.");
+                    std::process::exit(4);
+                }
+            }
+        }
+
+        Dump {
+            opts,
+            item_data,
+            profile,
+            position_scale,
+            plot_opts,
+            output,
+            archive,
+            slot_binds: SlotBinds::default(),
+            exclusive: HashMap::new(),
+            zones: 0,
+            excluded_zones: 0,
+            last_group: None,
+            html_maps: Vec::new(),
+            regions: BTreeMap::new(),
+            snapshot: Vec::new(),
+            warnings: Warnings::new(opts.color.enabled(atty::Stream::Stderr)),
+            timings: Vec::new(),
+        }
+    }
+
+    /// `parse_time` is how long reading the zone's records took, recorded with `--timing`.
+    fn zone(&mut self, group: &str, name: &str, bounds: Option<Bounds>, zone_treasures: Vec<ZoneTreasure>, parse_time: Duration) {
+        let opts = self.opts;
+        let mut zone_treasures = zone_treasures;
+        if let Some(command) = opts.filter_cmd.as_ref() {
+            zone_treasures = match filter::filter_zone(command, group, name, &zone_treasures) {
+                Ok(filtered) => filtered,
+                Err(err) => {
+                    eprintln!("Unable to filter {} through {:?}, skipping it. Error: {}", name, command, err);
+                    return;
+                }
+            };
+        }
+        // Zones the filter failed on aren't dumped, so they don't count towards --limit
+        self.zones += 1;
+        for treasure in zone_treasures.iter().filter(|t| t.spawn_chance == 0) {
+            self.warnings.warn(WarningKind::Unspawnable, Some(name), Some(treasure.id), String::from("Spawn chance is 0%, so the chest never appears"));
+        }
+        if opts.hide_unspawnable {
+            zone_treasures.retain(|t| t.spawn_chance != 0);
+        }
+        if opts.warn_shared_positions {
+            let mut positions: BTreeMap<(i16, i16), Vec<String>> = BTreeMap::new();
+            for treasure in &zone_treasures {
+                positions.entry((treasure.pos_x, treasure.pos_y)).or_default().push(treasure.id.to_string());
+            }
+            for ((x, y), ids) in positions.into_iter().filter(|(_, ids)| ids.len() > 1) {
+                self.warnings.warn(WarningKind::SharedPosition, Some(name), None, format!("Chests {} are all at ({}, {})", ids.join(", "), x, y));
+            }
+        }
+        if opts.warn_empty_items {
+            for treasure in zone_treasures.iter().filter(|t| t.gil_chance < 100) {
+                let empty = empty_slots(treasure, &self.profile.split);
+                if !empty.is_empty() {
+                    self.warnings.warn(WarningKind::EmptyItem, Some(name), Some(treasure.id),
+                                       format!("Gives an item {}% of the time, but {} {} item ID 0", 100 - treasure.gil_chance, empty.join(", "), if empty.len() == 1 { "holds" } else { "hold" }));
+                }
+            }
+        }
+        match opts.sort_by {
+            Some(SortBy::Rarity) => {
+                let item_data = self.item_data;
+                zone_treasures.sort_by_key(|t| Reverse(item_data.rarity_of(&t.all_items())));
+            },
+            Some(SortBy::Position) => zone_treasures.sort_by_key(|t| (t.pos_y, t.pos_x)),
+            None => {},
+        }
+
+        if let Some(dir) = self.output.as_ref().filter(|_| self.archive.is_none()) {
+            if let Err(err) = std::fs::DirBuilder::new()
+                .recursive(true)
+                .create(dir.join(utils::safe_file_name(group))) {
+                eprintln!("Unable to create file directory. Error: {}", err);
+            }
+        }
+        // Each format's file, and the map, take this path with their own extension
+        let base_path = self.output.as_ref().map(|dir| zone_path(dir, group, name));
+        if base_path.is_some() {
+            for original in [group, name].iter() {
+                let safe = utils::safe_file_name(original);
+                if safe != *original {
+                    self.warnings.warn(WarningKind::RenamedFile, Some(name), None, format!("{:?} can't be used as a file name, so it was written as {:?}", original, safe));
+                }
+            }
+        }
+
+        let render_started = Instant::now();
+        let stacked = match zone_treasures.first() {
+            Some(first) => zone_treasures.len() > 1 && zone_treasures.iter().all(|t| (t.pos_x, t.pos_y) == (first.pos_x, first.pos_y)),
+            None => true,
+        };
+        if stacked && (opts.create_maps || opts.maps_html.is_some()) {
+            let detail = match zone_treasures.first() {
+                Some(first) => format!("All {} treasures are at ({}, {}). Skipping the map", zone_treasures.len(), first.pos_x, first.pos_y),
+                None => String::from("No treasures to place. Skipping the map"),
+            };
+            self.warnings.warn(WarningKind::StackedPositions, Some(name), None, detail);
+        } else if opts.create_maps || opts.maps_html.is_some() {
+            match plotter::render_svg(name, &zone_treasures, bounds, self.item_data, &self.plot_opts) {
+                Ok(svg) => {
+                    if opts.create_maps {
+                        let svg_path = base_path.as_ref().unwrap().with_extension("svg");
+                        if let Err(err) = write_output(&mut self.archive, &svg_path, svg.as_bytes()) {
+                            eprintln!("Failed to write SVG map for {}. Error: {}", name, err);
+                        }
+                    }
+                    if opts.maps_html.is_some() {
+                        self.html_maps.push((group.to_string(), name.to_string(), svg));
+                    }
+                },
+                Err(err) => self.warnings.warn(WarningKind::MapFailed, Some(name), None, format!("No map was drawn. {}", err)),
+            }
+        }
+        if opts.timing {
+            self.timings.push(ZoneTiming { zone: format!("{}/{}", group, name), parse: parse_time, render: render_started.elapsed() });
+        }
+
+        let rows = self.resolve_rows(group, name, &zone_treasures);
+
+        if opts.has_format(OutputFormat::Bincode) || opts.is_database() {
+            self.snapshot.push((group.to_string(), name.to_string(), zone_treasures));
+            return;
+        }
+        let rows = if opts.rare_focus { self.rare_rows(rows) } else { rows };
+
+        for &format in &opts.format {
+            let writer_path = base_path.as_ref().map(|path| path.with_extension(format.extension()));
+            self.write_zone(format, group, name, &zone_treasures, &rows, writer_path.as_ref());
+        }
+    }
+
+    /// Writes the zone in one of the per-zone formats, to `writer_path` or else stdout.
+    fn write_zone(&mut self, format: OutputFormat, group: &str, name: &str, zone_treasures: &[ZoneTreasure], rows: &[Row], writer_path: Option<&PathBuf>) {
+        let opts = self.opts;
+        let (writer, continued) = match writer_path {
+            Some(_) if self.archive.is_some() => (OutputWriter::Buffer(Vec::new()), false),
+            Some(file_path) => {
+                match create_output(file_path, opts.append) {
+                    Ok((file, continued)) => (OutputWriter::File(file), continued),
+                    Err(err) => { eprintln!("Error creating file {:?}. Error: {}", file_path, err); return; }
+                }
+            },
+            None => (OutputWriter::Stdout(std::io::stdout()), false)
+        };
+        let mut writer = LineEndings::new(writer, opts.crlf && (format == OutputFormat::Text || format == OutputFormat::Checklist));
+
+        match format {
+            OutputFormat::Text => {
+                let color = writer_path.is_none() && opts.color.enabled(atty::Stream::Stdout);
+                let res = if opts.rare_focus {
+                    self.write_rare_focus(&mut writer, name, rows, continued)
+                } else if opts.loot_set {
+                    self.write_loot_sets(&mut writer, name, rows, continued)
+                } else {
+                    self.write_text(&mut writer, group, name, zone_treasures, rows, color, continued)
+                };
+                if let Err(e) = res {
+                    eprintln!("Error writing to file. {}", e);
+                }
+            },
+            OutputFormat::Json | OutputFormat::CanonicalJson => {
+                let canonical = format == OutputFormat::CanonicalJson;
+                let mut rows = rows.to_vec();
+                if canonical {
+                    rows.sort_by_key(|(treasure, _)| treasure.id);
+                }
+                let treasures = self.json_treasures(group, rows);
+                let res = if canonical {
+                    output::write_canonical_json(&mut writer, group, name, treasures)
+                } else {
+                    output::write_json(&mut writer, group, name, treasures)
+                };
+                if let Err(e) = res {
+                    eprintln!("Error writing to file. {}", e);
+                }
+            },
+            OutputFormat::Lua => {
+                let treasures = self.json_treasures(group, rows.to_vec());
+                if let Err(e) = output::write_lua(&mut writer, name, treasures) {
+                    eprintln!("Error writing to file. {}", e);
+                }
+            },
+            OutputFormat::GeoJson => {
+                let features = rows.iter().cloned().map(|(treasure, names)| {
+                    let mut feature = output::geojson_feature(&treasure, names, &self.plot_opts);
+                    self.annotate(&mut feature["properties"], group, &treasure);
+                    feature
+                }).collect();
+                if let Err(e) = output::write_geojson(&mut writer, name, features) {
+                    eprintln!("Error writing to file. {}", e);
+                }
+            },
+            OutputFormat::Checklist => {
+                // Zones written one after another to stdout only need their group's heading once.
+                let new_group = writer_path.is_some() || self.last_group.as_ref().map(|last| last != group).unwrap_or(true);
+                if let Err(e) = write_checklist(&mut writer, group, name, rows, new_group && !continued) {
+                    eprintln!("Error writing to file. {}", e);
+                }
+                self.last_group = Some(group.to_string());
+            },
+            OutputFormat::Bincode => unreachable!(),
+            #[cfg(feature = "sqlite")]
+            OutputFormat::Sqlite => unreachable!(),
+        }
+        if let Err(e) = writer.flush() { eprintln!("Error writing to file. {}", e); }
+        if let (Some(file_path), OutputWriter::Buffer(contents)) = (writer_path, writer.into_inner()) {
+            if let Err(err) = write_output(&mut self.archive, file_path, &contents) {
+                eprintln!("Error adding {:?} to the archive. Error: {}", file_path, err);
+            }
+        }
+    }
+
+    fn add_to_region(&mut self, group: &str, zone: &ZoneData, zone_treasures: &[ZoneTreasure]) {
+        let hide = self.opts.hide_unspawnable;
+        match zone.world_offset {
+            Some(world_offset) => self.regions.entry(group.to_string()).or_default().push(RegionZone {
+                name: zone.name.clone(),
+                bounds: zone.bounds,
+                world_offset,
+                treasures: zone_treasures.iter().filter(|t| !hide || t.spawn_chance != 0).copied().collect(),
+            }),
+            None => self.warnings.warn(WarningKind::MissingWorldOffset, Some(&zone.name), None, format!("No world offset, so it's left off the {} map", group)),
+        }
+    }
+
+    /// The rows as JSON treasures, in world units and with the properties asked for by the options.
+    fn json_treasures(&self, group: &str, rows: Vec<Row>) -> Vec<serde_json::Value> {
+        rows.into_iter().map(|(treasure, names)| {
+            let mut value = output::json_treasure(&treasure, names, self.item_data);
+            if self.position_scale != 1.0 {
+                let (x, y) = self.world_position(&treasure);
+                value["pos_x"] = x.into();
+                value["pos_y"] = y.into();
+            }
+            self.annotate(&mut value, group, &treasure);
+            value
+        }).collect()
+    }
+
+    /// The treasure's position in world units, its raw position divided by the position scale.
+    fn world_position(&self, treasure: &ZoneTreasure) -> (f64, f64) {
+        (treasure.pos_x as f64 / self.position_scale, treasure.pos_y as f64 / self.position_scale)
+    }
+
+    /// Adds the properties asked for by --fold-identical, --highlight, --exclusive-loot, --gil-range and --rare-focus to a JSON treasure.
+    fn annotate(&self, properties: &mut serde_json::Value, group: &str, treasure: &ZoneTreasure) {
+        let opts = self.opts;
+        if opts.gil_range {
+            properties["gil"] = serde_json::json!({ "common": treasure.gil_amount, "rare": treasure.rare_gil_amount });
+        }
+        if opts.loot_set {
+            let item_data = self.item_data;
+            properties["loot_set"] = loot_set(treasure, &self.profile.split).into_iter().map(|(id, chance, armlet_chance)| match id {
+                Some(id) => serde_json::json!({ "id": id, "name": item_data.name(id), "chance": chance, "armlet_chance": armlet_chance }),
+                None => serde_json::json!({ "gil": { "amount": treasure.gil_amount, "rare_amount": treasure.rare_gil_amount }, "chance": chance, "armlet_chance": armlet_chance }),
+            }).collect::<Vec<_>>().into();
+        }
+        if opts.rare_focus {
+            properties["rare_chance"] = rare_chance(treasure, &self.profile.split).into();
+        }
+        if opts.fold_identical {
+            properties["fold"] = match fold(treasure) {
+                Some(Fold::Guaranteed) => "guaranteed".into(),
+                Some(Fold::SameRare) => "same-rare".into(),
+                None => serde_json::Value::Null,
+            };
+        }
+        if opts.highlight.is_some() {
+            properties["highlighted"] = self.plot_opts.highlighted(treasure).into();
+        }
+        if opts.exclusive_loot {
+            let exclusive = treasure.all_items().iter().copied().filter(|id| self.is_exclusive(group, *id)).collect::<Vec<_>>();
+            properties["exclusive_items"] = exclusive.into();
+        }
+    }
+
+    /// Resolves each treasure's item names, raising warnings and binding respawn slots along the way.
+    pub(super) fn resolve_rows(&mut self, group: &str, name: &str, zone_treasures: &[ZoneTreasure]) -> Vec<Row> {
+        let opts = self.opts;
+        let mut seen_ids = HashSet::new();
+        let mut rows = Vec::with_capacity(zone_treasures.len());
+        for treasure in zone_treasures {
+            if !seen_ids.insert(treasure.id) {
+                self.warnings.warn(WarningKind::DuplicateId, Some(name), Some(treasure.id), String::from("Treasure ID appears more than once"));
+            }
+            if treasure.spawn_chance > 100 || treasure.gil_chance > 100 {
+                self.warnings.warn(WarningKind::BadChance, Some(name), Some(treasure.id), format!("Chance out of range (spawn {}%, gil {}%)", treasure.spawn_chance, treasure.gil_chance));
+            }
+            let items = treasure.all_items();
+            let mut names = [
+                item_name(self.item_data, &mut self.warnings, &opts.empty_name, name, treasure, items[0]),
+                item_name(self.item_data, &mut self.warnings, &opts.empty_name, name, treasure, items[1]),
+                item_name(self.item_data, &mut self.warnings, &opts.empty_name, name, treasure, items[2]),
+                item_name(self.item_data, &mut self.warnings, &opts.empty_name, name, treasure, items[3]),
+            ];
+            if opts.pretty_names {
+                for item in names.iter_mut() {
+                    *item = pretty_name(item, opts.title_case);
+                }
+            }
+            self.slot_binds.record(name, group, treasure, &names[0]);
+            rows.push((*treasure, names));
+        }
+        rows
+    }
+
+    /// Writes the zone's text table. `color` highlights rows with ANSI codes rather than a leading *.
+    /// `continued` leaves out the zone name and header, for appending to a table already written.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn write_text<W: Write>(&self, writer: &mut W, group: &str, name: &str, zone_treasures: &[ZoneTreasure], rows: &[Row], color: bool, continued: bool) -> IOResult<()> {
+        let opts = self.opts;
+        if !continued {
+            writeln!(writer, "{}", name)?;
+            if !opts.no_header {
+                write_header(writer, &self.profile.split, opts.gil_range, if opts.relative_positions { Some(relative_width(opts.precision)) } else { None })?;
+            }
+        }
+        let relative = if opts.relative_positions { normalized_positions(zone_treasures) } else { Vec::new() };
+        for (i, (treasure, names)) in rows.iter().enumerate() {
+            let mut names = names.clone();
+            for (name, id) in names.iter_mut().zip(treasure.all_items().iter()) {
+                if self.is_exclusive(group, *id) { name.insert(0, '*'); }
+            }
+            if opts.fold_identical {
+                match fold(treasure) {
+                    Some(Fold::Guaranteed) => {
+                        names[0].push_str(" (guaranteed)");
+                        for name in names[1..].iter_mut() { name.clear(); }
+                    },
+                    Some(Fold::SameRare) => {
+                        names[2] = String::from("(same)");
+                        names[3] = String::from("(same)");
+                    },
+                    None => {},
+                }
+            }
+            let [first_item, second_item, rare_first_item, rare_second_item] = &names;
+            let (x, y) = self.world_position(treasure);
+            let (start, end) = match (self.plot_opts.highlighted(treasure), color) {
+                (false, true) if opts.colorize => (likelihood_color(treasure, &self.profile.split), "\x1b[0m"),
+                (false, _) => ("\t", ""),
+                (true, true) => ("\t\x1b[1;33m", "\x1b[0m"),
+                (true, false) => ("*\t", ""),
+            };
+            if opts.gil_range {
+                write!(writer, "{}{:<3}{:<6x}{:<6}{:<6}{:11}{:20}{:20}{:20}{:20}{:6}{:6}", start, treasure.id, treasure.respawn_slot, treasure.spawn_chance, treasure.gil_chance, gil_range(treasure), first_item, second_item, rare_first_item, rare_second_item, x, y)?;
+            } else {
+                write!(writer, "{}{:<3}{:<6x}{:<6}{:<6}{:<6}{:20}{:20}{:20}{:20}{:5}{:6}{:6}", start, treasure.id, treasure.respawn_slot, treasure.spawn_chance, treasure.gil_chance, treasure.gil_amount, first_item, second_item, rare_first_item, rare_second_item, treasure.rare_gil_amount, x, y)?;
+            }
+            if let Some((x, y)) = relative.get(i) {
+                write!(writer, "{:>2$.3$}{:>2$.3$}", x, y, relative_width(opts.precision), opts.precision)?;
+            }
+            writeln!(writer, "{}", end)?;
+        }
+        Ok(())
+    }
+
+    /// The rows whose second Diamond Armlet slot holds an item not found in the common slots, least likely first.
+    fn rare_rows(&self, rows: Vec<Row>) -> Vec<Row> {
+        let split = &self.profile.split;
+        let mut rows = rows.into_iter()
+            .filter(|(t, _)| !t.common_items().contains(&t.rare_second_item))
+            .collect::<Vec<_>>();
+        rows.sort_by(|(a, _), (b, _)| rare_chance(a, split).partial_cmp(&rare_chance(b, split)).unwrap());
+        rows
+    }
+
+    /// Writes the `--rare-focus` table: each chest's second Diamond Armlet item and the chance of getting it.
+    fn write_rare_focus<W: Write>(&self, writer: &mut W, name: &str, rows: &[Row], continued: bool) -> IOResult<()> {
+        let opts = self.opts;
+        if !continued {
+            writeln!(writer, "{} (rare drops)", name)?;
+            if !opts.no_header {
+                writeln!(writer, "\t{:3}{:20}{:>10}", "ID", format!("DA 2 ({}%)", self.profile.split.rare_second_item), "Chance")?;
+                writeln!(writer, "\t{:=<1$}", "=", 33)?;
+            }
+        }
+        for (treasure, names) in rows {
+            writeln!(writer, "\t{:<3}{:20}{:>9.3$}%", treasure.id, names[3], rare_chance(treasure, &self.profile.split), opts.precision)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the `--loot-set` list: each chest's distinct items and gil, with the chance of each without and with the Diamond Armlet.
+    fn write_loot_sets<W: Write>(&self, writer: &mut W, name: &str, rows: &[Row], continued: bool) -> IOResult<()> {
+        let opts = self.opts;
+        if !continued {
+            writeln!(writer, "{} (loot sets)", name)?;
+            if !opts.no_header {
+                writeln!(writer, "\t{:3}{:20}{:>10}{:>10}", "ID", "Loot", "Chance", "DA Chance")?;
+                writeln!(writer, "\t{:=<1$}", "=", 43)?;
+            }
+        }
+        for (treasure, names) in rows {
+            let slots = treasure.all_items();
+            for (i, (id, chance, armlet_chance)) in loot_set(treasure, &self.profile.split).into_iter().enumerate() {
+                let loot = match id {
+                    Some(id) => names[slots.iter().position(|slot| *slot == id).unwrap()].clone(),
+                    None => format!("{} gil", gil_range(treasure)),
+                };
+                let chest = if i == 0 { treasure.id.to_string() } else { String::new() };
+                writeln!(writer, "\t{:<3}{:20}{:>9.4$}%{:>9.4$}%", chest, loot, chance, armlet_chance, opts.precision)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes each record's bytes next to the zone's output, or to stdout when there is no output directory.
+    fn raw_records(&mut self, group: &str, name: &str, records: &[[u8; RECORD_SIZE]]) {
+        let raw_path = self.output.as_ref().map(|dir| dir.join(utils::safe_file_name(group)).join(format!("{}.raw.txt", utils::safe_file_name(name))));
+        let writer = match raw_path.as_ref() {
+            Some(_) if self.archive.is_some() => OutputWriter::Buffer(Vec::new()),
+            Some(raw_path) => {
+                match File::create(raw_path) { Ok(file) => OutputWriter::File(file), Err(err) => { eprintln!("Error creating file {:?}. Error: {}", raw_path, err); return; }}
+            },
+            None => OutputWriter::Stdout(std::io::stdout())
+        };
+        let mut writer = LineEndings::new(writer, self.opts.crlf);
+        let res = writeln!(writer, "{} (raw records)", name).and_then(|_| {
+            for record in records {
+                let id = u32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+                writeln!(writer, "\t{:<3}{}", id, record_hex(record))?;
+            }
+            writer.flush()
+        });
+        if let Err(e) = res { eprintln!("Error writing to file. {}", e); }
+        if let (Some(raw_path), OutputWriter::Buffer(contents)) = (raw_path.as_ref(), writer.into_inner()) {
+            if let Err(err) = write_output(&mut self.archive, raw_path, &contents) {
+                eprintln!("Error adding {:?} to the archive. Error: {}", raw_path, err);
+            }
+        }
+    }
+
+    /// Writes the `--raw-sidecar` file: each record's bytes as one hex string, keyed by the ID they start with.
+    fn raw_sidecar(&mut self, group: &str, name: &str, records: &[[u8; RECORD_SIZE]]) {
+        let sidecar_path = match self.output.as_ref() {
+            Some(dir) => dir.join(utils::safe_file_name(group)).join(format!("{}.raw.json", utils::safe_file_name(name))),
+            None => return,
+        };
+        let records = records.iter()
+            .map(|record| (u32::from_le_bytes([record[0], record[1], record[2], record[3]]), hex::encode(&record[..])))
+            .collect::<BTreeMap<_, _>>();
+        let sidecar = serde_json::json!({ "zone": name, "record_size": RECORD_SIZE, "records": records });
+        let res = serde_json::to_vec_pretty(&sidecar).map_err(TreasureError::from)
+            .and_then(|contents| Ok(write_output(&mut self.archive, &sidecar_path, &contents)?));
+        if let Err(err) = res {
+            eprintln!("Error writing {:?}. Error: {}", sidecar_path, err);
+        }
+    }
+
+    /// Whether `--limit` zones have been dumped already, in which case the output is reported as partial.
+    fn limit_reached(&self) -> bool {
+        match self.opts.limit {
+            Some(limit) if self.zones >= limit => {
+                eprintln!("Stopped after {} zones (limit). The output is partial.", self.zones);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the zone was excluded with `--exclude-group` or `--exclude-zone`, counting it if so.
+    fn excluded(&mut self, group: &str, zone: &str) -> bool {
+        let excluded = self.opts.exclude_group.iter().any(|g| g == group) || self.opts.exclude_zone.iter().any(|z| z == zone);
+        if excluded { self.excluded_zones += 1; }
+        excluded
+    }
+
+    fn is_exclusive(&self, group: &str, id: u16) -> bool {
+        self.exclusive.get(&id).map(|g| g == group).unwrap_or(false)
+    }
+
+    fn finish(self) {
+        #[cfg(feature = "sqlite")]
+        {
+            if self.opts.has_format(OutputFormat::Sqlite) {
+                let db_path = self.opts.output.as_ref().unwrap();
+                if let Err(err) = sqlite::write_database(db_path, &self.snapshot, self.item_data) {
+                    eprintln!("Unable to write the database {:?}. Error: {}", db_path, err);
+                    std::process::exit(4);
+                }
+            }
+        }
+        let Dump { opts, item_data, plot_opts, output, mut archive, slot_binds, html_maps, regions, snapshot, mut warnings, excluded_zones, timings, .. } = self;
+
+        for (group, zones) in &regions {
+            let svg_path = output.as_ref().unwrap().join(utils::safe_file_name(group)).with_extension("svg");
+            let res = plotter::render_region_svg(group, zones, item_data, &plot_opts)
+                .and_then(|svg| Ok(write_output(&mut archive, &svg_path, svg.as_bytes())?));
+            if let Err(err) = res {
+                warnings.warn(WarningKind::MapFailed, None, None, format!("No merged map was drawn for {}. {}", group, err));
+            }
+        }
+
+        if excluded_zones > 0 {
+            eprintln!("Excluded {} zones.", excluded_zones);
+        }
+
+        if opts.timing {
+            print_timings(timings);
+        }
+
+        if opts.has_format(OutputFormat::Bincode) {
+            let mut snapshot_out = match output.as_ref() {
+                Some(_) if archive.is_some() => OutputWriter::Buffer(Vec::new()),
+                Some(out_dir) => match File::create(out_dir.join(SNAPSHOT_FILE)) {
+                    Ok(file) => OutputWriter::File(file),
+                    Err(err) => { eprintln!("Unable to create {}.\nError: {}", SNAPSHOT_FILE, err); std::process::exit(4); }
+                },
+                None => OutputWriter::Stdout(std::io::stdout())
+            };
+            let res = bincode::serialize_into(&mut snapshot_out, &snapshot).map_err(TreasureError::from).and_then(|_| match snapshot_out {
+                OutputWriter::Buffer(contents) => Ok(write_output(&mut archive, Path::new(SNAPSHOT_FILE), &contents)?),
+                _ => Ok(()),
+            });
+            if let Err(err) = res {
+                eprintln!("Unable to write {}. Error: {}", SNAPSHOT_FILE, err);
+            }
+        }
+
+        if let Some(warnings_path) = opts.warnings_json.as_ref() {
+            if let Err(err) = warnings.write_json(warnings_path) {
+                eprintln!("Unable to write warnings to {:?}. Error: {}", warnings_path, err);
+            }
+        }
+
+        if let Some(html_path) = opts.maps_html.as_ref() {
+            if let Err(err) = File::create(html_path).and_then(|mut file| plotter::write_html(&mut file, &html_maps)) {
+                eprintln!("Unable to write HTML maps to {:?}. Error: {}", html_path, err);
+            }
+        }
+
+        let json_slots = opts.has_format(OutputFormat::Json) || opts.has_format(OutputFormat::CanonicalJson);
+        let slots_file = if json_slots { "respawn-slots.json" } else { "respawn-slots.txt" };
+        let slot_out = if let Some(slots_path) = opts.slots_output.as_ref() {
+            if slots_path.as_path() == Path::new("-") {
+                OutputWriter::Stdout(std::io::stdout())
+            } else {
+                match File::create(slots_path) {
+                    Ok(file) => OutputWriter::File(file),
+                    Err(err) => { eprintln!("Unable to create {:?}.\nError: {}", slots_path, err); return; }
+                }
+            }
+        } else if archive.is_some() {
+            OutputWriter::Buffer(Vec::new())
+        } else if let Some(out_dir) = output.as_ref() {
+            let slots = out_dir.join(slots_file);
+            match File::create(slots) {
+                Ok(file) => OutputWriter::File(file),
+                Err(err) => { eprintln!("Unable to create {}.\nError: {}", slots_file, err); return; }
+            }
+        } else { OutputWriter::Stdout(std::io::stdout()) };
+        let mut slot_out = LineEndings::new(slot_out, opts.crlf && !json_slots);
+
+        if json_slots {
+            slot_binds.write_json(&mut slot_out).expect("Writing respawn-slots.json");
+        } else {
+            slot_binds.write_text(&mut slot_out, opts.hide_empty_slots).expect("Writing respawn-slots.txt");
+        }
+
+        if let OutputWriter::Buffer(contents) = slot_out.into_inner() {
+            if let Err(err) = write_output(&mut archive, Path::new(slots_file), &contents) {
+                eprintln!("Unable to add {} to the archive. Error: {}", slots_file, err);
+            }
+        }
+        if let Some(mut zip) = archive {
+            if let Err(err) = zip.finish() {
+                eprintln!("Unable to finish the archive {:?}. Error: {}", opts.output.as_ref().unwrap(), err);
+            }
+        }
+
+        if utils::interrupted() {
+            std::process::exit(utils::INTERRUPTED_EXIT_CODE);
+        }
+    }
+}
+
+/// Prints the slowest zones to stderr, so it stays out of output written to stdout, followed by the totals.
+fn print_timings(mut timings: Vec<ZoneTiming>) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let parse = timings.iter().map(|t| t.parse).sum::<Duration>();
+    let render = timings.iter().map(|t| t.render).sum::<Duration>();
+    timings.sort_by_key(|t| Reverse(t.parse + t.render));
+    eprintln!("{:>10} {:>10}  Zone", "Parse ms", "Render ms");
+    for timing in timings.iter().take(TIMING_ROWS) {
+        eprintln!("{:>10.1} {:>10.1}  {}", ms(timing.parse), ms(timing.render), timing.zone);
+    }
+    eprintln!("{:>10.1} {:>10.1}  Total over {} zones", ms(parse), ms(render), timings.len());
+}
+
+/// Writes the zone's chests as a Markdown task list, under a heading for the zone and, when `group_heading`
+/// is set, one for its group.
+fn write_checklist<W: Write>(writer: &mut W, group: &str, name: &str, rows: &[Row], group_heading: bool) -> IOResult<()> {
+    if group_heading {
+        writeln!(writer, "# {}", group)?;
+        writeln!(writer)?;
+    }
+    writeln!(writer, "## {}", name)?;
+    writeln!(writer)?;
+    for (treasure, names) in rows {
+        write!(writer, "- [ ] Chest {}: {}, {} (Diamond Armlet: {}, {})", treasure.id + 1, names[0], names[1], names[2], names[3])?;
+        if treasure.gil_chance > 0 {
+            write!(writer, ", or {} gil ({}%)", gil_range(treasure), treasure.gil_chance)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer)
+}
+
+/// Percent chance of a chest giving its second Diamond Armlet item: it has to spawn, hold an item rather
+/// than gil, and then pick that slot.
+fn rare_chance(treasure: &ZoneTreasure, split: &DropSplit) -> f64 {
+    let spawn = treasure.spawn_chance as f64 / 100.0;
+    let item = (100 - std::cmp::min(treasure.gil_chance, 100)) as f64 / 100.0;
+    spawn * item * split.rare_second_item as f64
+}
+
+/// The start of a `--colorize` row: green when the chest's likeliest drop comes up on at least half of its
+/// checks, yellow from a fifth, and red below that.
+fn likelihood_color(treasure: &ZoneTreasure, split: &DropSplit) -> &'static str {
+    let best = loot_set(treasure, split).into_iter().map(|(_, chance, _)| chance).fold(0.0, f64::max);
+    let likelihood = treasure.spawn_chance as f64 * best / 100.0;
+    if likelihood >= 50.0 {
+        "\t\x1b[32m"
+    } else if likelihood >= 20.0 {
+        "\t\x1b[33m"
+    } else {
+        "\t\x1b[31m"
+    }
+}
+
+/// A distinct item a chest can give, or its gil when `None`, with the percent chance of getting it once the
+/// chest is opened, without and then with the Diamond Armlet.
+type Loot = (Option<u16>, f64, f64);
+
+/// Collapses the chest's four item slots into its distinct items, summing the chances of slots holding the
+/// same item, followed by gil when the chest can give any.
+fn loot_set(treasure: &ZoneTreasure, split: &DropSplit) -> Vec<Loot> {
+    let gil = std::cmp::min(treasure.gil_chance, 100) as f64;
+    let item = (100.0 - gil) / 100.0;
+    let chances = [(split.first_item, 0), (split.second_item, 0), (0, split.rare_first_item), (0, split.rare_second_item)];
+    let mut loot: Vec<Loot> = Vec::new();
+    for (id, (common, armlet)) in treasure.all_items().iter().zip(chances.iter()) {
+        let (common, armlet) = (item * *common as f64, item * *armlet as f64);
+        match loot.iter_mut().find(|(other, _, _)| *other == Some(*id)) {
+            Some(entry) => {
+                entry.1 += common;
+                entry.2 += armlet;
+            },
+            None => loot.push((Some(*id), common, armlet)),
+        }
+    }
+    loot.retain(|(_, common, armlet)| *common > 0.0 || *armlet > 0.0);
+    if gil > 0.0 {
+        loot.push((None, gil, gil));
+    }
+    loot
+}
+
+/// The names of the chest's item slots that can be picked under `split` but hold item ID 0.
+fn empty_slots(treasure: &ZoneTreasure, split: &DropSplit) -> Vec<&'static str> {
+    let slots = [
+        ("the first item", split.first_item),
+        ("the second item", split.second_item),
+        ("the first Diamond Armlet item", split.rare_first_item),
+        ("the second Diamond Armlet item", split.rare_second_item),
+    ];
+    slots.iter().zip(treasure.all_items().iter())
+        .filter(|((_, chance), id)| *chance > 0 && **id == 0)
+        .map(|((slot, _), _)| *slot)
+        .collect()
+}
+
+/// How `--fold-identical` collapses a chest's item slots.
+enum Fold {
+    /// Every slot holds the same item
+    Guaranteed,
+    /// The Diamond Armlet slots repeat the common slots
+    SameRare,
+}
+
+fn fold(treasure: &ZoneTreasure) -> Option<Fold> {
+    let [first, second, rare_first, rare_second] = treasure.all_items();
+    if first == second && second == rare_first && rare_first == rare_second {
+        Some(Fold::Guaranteed)
+    } else if first == rare_first && second == rare_second {
+        Some(Fold::SameRare)
+    } else {
+        None
+    }
+}
+
+fn item_name(item_data: &ItemData, warnings: &mut Warnings, empty_name: &str, zone: &str, treasure: &ZoneTreasure, id: u16) -> String {
+    match item_data.name(id) {
+        Some(name) if name.is_empty() => empty_name.to_string(),
+        Some(name) => name.clone(),
+        None => {
+            warnings.warn(WarningKind::UnknownItem, Some(zone), Some(treasure.id), format!("Unknown item ID 0x{:04x}", id));
+            format!("Unknown 0x{:04x}", id)
+        }
+    }
+}
+
+/// Trims the name and collapses runs of whitespace within it. With `title_case`, names written entirely
+/// in capitals are also changed to capitalize just the first letter of each word; names with any
+/// lowercase letter are assumed to be cased on purpose and left alone.
+fn pretty_name(name: &str, title_case: bool) -> String {
+    let words = name.split_whitespace();
+    if !title_case || name.chars().any(char::is_lowercase) {
+        return words.collect::<Vec<_>>().join(" ");
+    }
+    words.map(|word| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect::<String>(),
+            None => String::new(),
+        }
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// Creates and removes a file in `dir`, so an unwritable output fails once up front rather than for every zone.
+fn check_writable(dir: &Path) -> IOResult<()> {
+    let probe = dir.join(".ff12tza-utils-write-test");
+    File::create(&probe)?;
+    std::fs::remove_file(&probe)
+}
+
+/// The path a zone's output files share before their extensions, with the group and zone name made safe to
+/// use as file names.
+fn zone_path(dir: &Path, group: &str, name: &str) -> PathBuf {
+    dir.join(utils::safe_file_name(group)).join(utils::safe_file_name(name))
+}
+
+/// Writes a whole output file, or adds it to the archive when the output is a zip.
+fn write_output(archive: &mut Option<ZipWriter<File>>, path: &Path, contents: &[u8]) -> IOResult<()> {
+    match archive {
+        Some(archive) => {
+            // Entry names always separate directories with forward slashes
+            let name = path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            archive.start_file(name, FileOptions::default())?;
+            archive.write_all(contents)
+        },
+        None => std::fs::write(path, contents),
+    }
+}
+
+/// Opens a zone's output file, appending when `append` is set. Also returns whether the file already had contents.
+fn create_output(path: &Path, append: bool) -> IOResult<(File, bool)> {
+    if !append {
+        return File::create(path).map(|file| (file, false));
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let continued = file.metadata()?.len() > 0;
+    Ok((file, continued))
+}
+
+/// The chest's gil as a range from the common amount to the Diamond Armlet amount, or one amount when they're equal.
+fn gil_range(treasure: &ZoneTreasure) -> String {
+    if treasure.gil_amount == treasure.rare_gil_amount {
+        treasure.gil_amount.to_string()
+    } else {
+        format!("{}\u{2013}{}", treasure.gil_amount, treasure.rare_gil_amount)
+    }
+}
+
+/// Width of the RelX and RelY columns, which hold a value between 0 and 1 and some padding.
+fn relative_width(precision: usize) -> usize {
+    std::cmp::max(precision + 4, 6)
+}
+
+/// `gil_range` merges the Gil and DGil columns into one, and `relative` is the width of the RelX and
+/// RelY columns, when they're shown.
+fn write_header<W: Write>(writer: &mut W, split: &DropSplit, gil_range: bool, relative: Option<usize>) -> IOResult<()> {
+    let first_item = format!("Item 1 ({}%)", split.first_item);
+    let second_item = format!("Item 2 ({}%)", split.second_item);
+    let rare_first_item = format!("DA 1 ({}%)", split.rare_first_item);
+    let rare_second_item = format!("DA 2 ({}%)", split.rare_second_item);
+    if gil_range {
+        write!(writer, "\t{:3}{:6}{:6}{:6}{:11}{:20}{:20}{:20}{:20}{:>6}{:>6}", "ID", "Slot", "Spn%", "Gil%", "Gil", first_item, second_item, rare_first_item, rare_second_item, "X", "Y")?;
+    } else {
+        write!(writer, "\t{:3}{:6}{:6}{:6}{:6}{:20}{:20}{:20}{:20}{:5}{:>6}{:>6}", "ID", "Slot", "Spn%", "Gil%", "Gil", first_item, second_item, rare_first_item, rare_second_item, "DGil", "X", "Y")?;
+    }
+    if let Some(width) = relative {
+        write!(writer, "{:>2$}{:>2$}", "RelX", "RelY", width)?;
+    }
+    writeln!(writer)
+        .and_then(|_| writeln!(writer, "\t{:=<1$}", "=", 124 + 2 * relative.unwrap_or(0)))
+}
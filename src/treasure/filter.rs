@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TreasureError;
+use crate::treasure::ZoneTreasure;
+
+/// What `--filter-cmd` reads on its stdin.
+#[derive(Serialize)]
+struct FilterInput<'a> {
+    group: &'a str,
+    name: &'a str,
+    treasures: &'a [ZoneTreasure],
+}
+
+/// What `--filter-cmd` has to write back. Anything besides the treasures is ignored.
+#[derive(Deserialize)]
+struct FilterOutput {
+    treasures: Vec<ZoneTreasure>,
+}
+
+/// Runs `command` through the shell with the zone as JSON on its stdin, and returns the treasures in the
+/// JSON it writes to stdout. The command exiting unsuccessfully is an error.
+pub fn filter_zone(command: &str, group: &str, name: &str, treasures: &[ZoneTreasure]) -> Result<Vec<ZoneTreasure>, TreasureError> {
+    let input = serde_json::to_vec(&FilterInput { group, name, treasures })?;
+    let mut child = shell(command).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+    let mut stdin = child.stdin.take().expect("Taking the filter command's stdin");
+    // Written from another thread, so a command writing before it has read everything can't deadlock
+    let feeder = std::thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output()?;
+    match feeder.join().expect("Writing to the filter command") {
+        // The command doesn't have to read its input
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => {},
+    }
+    if !output.status.success() {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("the filter command failed ({})", output.status)).into());
+    }
+    Ok(serde_json::from_slice::<FilterOutput>(&output.stdout)?.treasures)
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.args(&["/C", command]);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.args(&["-c", command]);
+    shell
+}
@@ -0,0 +1,62 @@
+use std::path::PathBuf;
+
+use crate::treasure::{parse_record, ZoneTreasure, RECORD_SIZE};
+
+/// Hex dumps the start of a zone script and lists offsets where a treasure table seems to begin,
+/// so zones missing from the treasure data can be added.
+pub fn inspect(file: PathBuf, bytes: usize, min_records: usize) {
+    let data = match std::fs::read(&file) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Unable to read {:?}. Error: {}", file, err);
+            std::process::exit(4);
+        }
+    };
+
+    println!("{:?} ({} bytes)", file, data.len());
+    for (row, chunk) in data[..std::cmp::min(bytes, data.len())].chunks(16).enumerate() {
+        let hex = chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ");
+        let text = chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }).collect::<String>();
+        println!("{:08x}  {:<47}  {}", row * 16, hex, text);
+    }
+
+    let candidates = candidate_tables(&data, min_records);
+    if candidates.is_empty() {
+        println!("No runs of {} or more plausible treasure records were found.", min_records);
+        return;
+    }
+    println!("Candidate treasure tables:");
+    for (offset, quantity) in candidates {
+        println!("\t\"offset\": {}, \"quantity\": {}\t(0x{:x})", offset, quantity, offset);
+    }
+}
+
+/// Whether the record at `index` in a table could be a treasure: IDs count up from 0 and both chances are percentages.
+fn plausible(treasure: &ZoneTreasure, index: usize) -> bool {
+    treasure.id as usize == index && treasure.spawn_chance <= 100 && treasure.gil_chance <= 100
+}
+
+/// Offsets where at least `min_records` plausible records follow each other, with how many there are.
+fn candidate_tables(data: &[u8], min_records: usize) -> Vec<(usize, usize)> {
+    let record_at = |offset: usize| {
+        let mut record = [0u8; RECORD_SIZE];
+        record.copy_from_slice(&data[offset..offset + RECORD_SIZE]);
+        parse_record(&record).ok()
+    };
+    let mut candidates = Vec::new();
+    let mut offset = 0;
+    while offset + RECORD_SIZE <= data.len() {
+        let mut quantity = 0;
+        while offset + (quantity + 1) * RECORD_SIZE <= data.len()
+            && record_at(offset + quantity * RECORD_SIZE).map(|t| plausible(&t, quantity)).unwrap_or(false) {
+            quantity += 1;
+        }
+        if quantity >= std::cmp::max(min_records, 1) {
+            candidates.push((offset, quantity));
+            offset += quantity * RECORD_SIZE;
+        } else {
+            offset += 1;
+        }
+    }
+    candidates
+}
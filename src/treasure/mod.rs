@@ -1,77 +1,283 @@
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::{Cursor, IoSlice, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, BufReader, Cursor, IoSlice, Read, Seek, SeekFrom, Write};
 use std::io::Result as IOResult;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use byteorder::{LE, ReadBytesExt};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use serde::export::fmt::Arguments;
 use walkdir::WalkDir;
 
 use crate::error::TreasureError;
-
+use crate::opt::{DumpTreasure, ItemFormat};
+
+#[cfg(feature = "tui_browse")]
+mod browse;
+mod cache;
+mod diff;
+mod dump;
+mod filter;
+mod inspect;
+mod output;
 mod plotter;
+mod profile;
+mod report;
+mod self_test;
+mod slots;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod warnings;
+mod watch;
+
+#[cfg(feature = "tui_browse")]
+pub use browse::browse;
+pub use diff::{diff_item_data, diff_vanilla};
+pub use dump::{dump_treasure, load_treasure};
+pub use inspect::inspect;
+pub use output::{geojson_feature, json_treasure, write_canonical_json, write_geojson, write_json, write_lua};
+pub use plotter::{render_svg, PlotError, PlotOptions};
+pub use report::{find, near, respawn_slots, route, scaffold_items, simulate, stats, used_items};
+pub use self_test::self_test;
+
+use profile::Profile;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TreasureData {
+    pub groups: HashMap<String, HashSet<String>>,
+    pub zones: HashMap<String, ZoneData>,
+}
 
-#[derive(Deserialize, Debug)]
-struct TreasureData {
-    groups: HashMap<String, HashSet<String>>,
-    zones: HashMap<String, ZoneData>,
+/// Item names by ID. Treasure records hold 16 bit item IDs, but the keys are 32 bit so item tables
+/// from versions or mods with a wider ID space can be read too. Look names up with [`ItemData::name`].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ItemData {
+    pub ids: HashMap<u32, String>,
+    /// Optional rarity tier per item ID, higher being rarer
+    #[serde(default)]
+    pub rarity: HashMap<u32, u8>,
+    /// Optional language of the names, such as "en" or "jp", reported when dumping
+    #[serde(default)]
+    pub language: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct ItemData {
-    ids: HashMap<u16, String>
+impl ItemData {
+    /// The highest rarity tier among the given items, if any of them have one.
+    fn rarity_of(&self, ids: &[u16]) -> Option<u8> {
+        ids.iter().filter_map(|id| self.rarity.get(&u32::from(*id))).copied().max()
+    }
+
+    /// The name of an item ID from a treasure record.
+    pub fn name(&self, id: u16) -> Option<&String> {
+        self.ids.get(&u32::from(id))
+    }
+
+    /// Replaces newlines, tabs and other control characters left in names from extraction, which would
+    /// otherwise break up tables, with [`CONTROL_PLACEHOLDER`]. Returns how many names were changed.
+    fn sanitize(&mut self) -> usize {
+        let mut sanitized = 0;
+        for name in self.ids.values_mut().filter(|name| name.chars().any(char::is_control)) {
+            *name = name.chars().map(|c| if c.is_control() { CONTROL_PLACEHOLDER } else { c }).collect();
+            sanitized += 1;
+        }
+        sanitized
+    }
+
+    /// Whether any ID is too wide to appear in a treasure record.
+    pub fn has_wide_ids(&self) -> bool {
+        self.ids.keys().any(|id| *id > u32::from(u16::MAX))
+    }
 }
 
-trait FromJsonPath {
+/// Shown in place of control characters in item names.
+const CONTROL_PLACEHOLDER: char = '\u{fffd}';
+
+trait FromDataPath {
     fn open(input: PathBuf) -> Result<Self, TreasureError>
         where Self: Sized;
 }
 
-impl FromJsonPath for TreasureData {
+impl FromDataPath for TreasureData {
     fn open(input: PathBuf) -> Result<TreasureData, TreasureError> {
+        if input.extension().map(|ext| ext.eq_ignore_ascii_case("csv")).unwrap_or(false) {
+            return TreasureData::from_csv(BufReader::new(File::open(input)?));
+        }
         Ok(serde_json::from_reader(File::open(input)?)?)
     }
 }
 
-impl FromJsonPath for ItemData {
+impl TreasureData {
+    /// Reads a zone table with a header row naming the stem, name, offset and quantity columns,
+    /// in any order. An optional group column adds each zone to the named group when not blank.
+    fn from_csv<R: BufRead>(reader: R) -> Result<TreasureData, TreasureError> {
+        let mut lines = reader.lines().enumerate().map(|(i, line)| (i + 1, line));
+        let header = match lines.next() {
+            Some((_, line)) => csv_fields(&line?),
+            None => return Err(TreasureError::Csv(1, String::from("missing header row"))),
+        };
+        let column = |name: &str| header.iter().position(|field| field.trim().eq_ignore_ascii_case(name));
+        let (stem, name, offset, quantity) = match (column("stem"), column("name"), column("offset"), column("quantity")) {
+            (Some(stem), Some(name), Some(offset), Some(quantity)) => (stem, name, offset, quantity),
+            _ => return Err(TreasureError::Csv(1, String::from("header must have stem, name, offset and quantity columns"))),
+        };
+        let group = column("group");
+
+        let mut data = TreasureData { groups: HashMap::new(), zones: HashMap::new() };
+        for (line_no, line) in lines {
+            let line = line?;
+            if line.trim().is_empty() { continue; }
+            let fields = csv_fields(&line);
+            let field = |i: usize| fields.get(i).map(|field| field.trim()).unwrap_or("");
+            let bad = |msg: String| TreasureError::Csv(line_no, msg);
+
+            let zone_stem = field(stem);
+            if zone_stem.is_empty() { return Err(bad(String::from("empty stem"))); }
+            let zone = ZoneData {
+                name: field(name).to_string(),
+                offset: field(offset).parse().map_err(|_| bad(format!("offset {:?} is not a whole number", field(offset))))?,
+                quantity: field(quantity).parse().map_err(|_| bad(format!("quantity {:?} is not a whole number up to {}", field(quantity), u16::MAX)))?,
+                bounds: None,
+                world_offset: None,
+            };
+            if data.zones.insert(zone_stem.to_string(), zone).is_some() {
+                return Err(bad(format!("zone {} appears more than once", zone_stem)));
+            }
+            if let Some(group) = group.map(field).filter(|group| !group.is_empty()) {
+                data.groups.entry(group.to_string()).or_default().insert(zone_stem.to_string());
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Quotes a CSV field when it holds a comma, quote or line break, doubling any quotes, so [`csv_fields`] reads it back.
+fn csv_field(field: &str) -> Cow<str> {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Splits a CSV line into its fields. Quoted fields may hold commas, and "" within them is a literal quote.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); fields.last_mut().unwrap().push('"'); },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+impl FromDataPath for ItemData {
     fn open(input: PathBuf) -> Result<ItemData, TreasureError> {
         Ok(serde_json::from_reader(File::open(input)?)?)
     }
 }
 
-#[derive(Deserialize, Debug)]
-struct ZoneData {
-    name: String,
-    offset: u64,
-    quantity: u16,
+impl ItemData {
+    /// Reads the game's item name resource, where the names are null terminated and the Nth name is item ID N.
+    fn from_game_text<R: Read>(reader: R) -> Result<ItemData, TreasureError> {
+        let mut reader = reader;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        let ids = bytes.split(|b| *b == 0)
+            .take(u16::MAX as usize + 1)
+            .enumerate()
+            .map(|(id, name)| (id as u32, String::from_utf8_lossy(name).into_owned()))
+            .collect();
+        Ok(ItemData { ids, rarity: HashMap::new(), language: None })
+    }
+}
+
+impl FromDataPath for Profile {
+    fn open(input: PathBuf) -> Result<Profile, TreasureError> {
+        Ok(serde_json::from_reader(File::open(input)?)?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ZoneData {
+    pub name: String,
+    pub offset: u64,
+    pub quantity: u16,
+    /// Known extent of the zone's map, in world coordinates
+    #[serde(default)]
+    pub bounds: Option<Bounds>,
+    /// Where the zone's origin lies in its group's shared world space, as [x, y], for --merge-maps
+    #[serde(default)]
+    pub world_offset: Option<(i32, i32)>,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min_x: i16,
+    pub min_y: i16,
+    pub max_x: i16,
+    pub max_y: i16,
+}
+
+impl Bounds {
+    fn contains(&self, treasure: &ZoneTreasure) -> bool {
+        (self.min_x..=self.max_x).contains(&treasure.pos_x) && (self.min_y..=self.max_y).contains(&treasure.pos_y)
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
 pub struct ZoneTreasure {
-    id: u32,
-    pos_x: i16,
-    pos_y: i16,
-    respawn_slot: u8,
-    spawn_chance: u8,
-    gil_chance: u8,
-    first_item: u16,
-    second_item: u16,
-    rare_first_item: u16,
-    rare_second_item: u16,
-    gil_amount: u16,
-    rare_gil_amount: u16,
-}
-
-fn get_data<T: FromJsonPath>(pb: PathBuf, name: &'static str, env_name: &'static str) -> T {
+    pub id: u32,
+    pub pos_x: i16,
+    pub pos_y: i16,
+    pub respawn_slot: u8,
+    pub spawn_chance: u8,
+    pub gil_chance: u8,
+    pub first_item: u16,
+    pub second_item: u16,
+    pub rare_first_item: u16,
+    pub rare_second_item: u16,
+    pub gil_amount: u16,
+    pub rare_gil_amount: u16,
+}
+
+impl ZoneTreasure {
+    fn common_items(&self) -> [u16; 2] { [self.first_item, self.second_item] }
+
+    fn rare_items(&self) -> [u16; 2] { [self.rare_first_item, self.rare_second_item] }
+
+    fn all_items(&self) -> [u16; 4] { [self.first_item, self.second_item, self.rare_first_item, self.rare_second_item] }
+}
+
+fn get_data<T: FromDataPath + Serialize + DeserializeOwned>(pb: PathBuf, name: &'static str, env_name: &'static str, use_cache: bool) -> T {
     if !pb.exists() {
         eprintln!("Missing {} data file!", name);
         eprintln!("Use the --{}-data option or the {}_DATA environment variable.", name, env_name);
         std::process::exit(2);
     }
-    match T::open(pb) {
-        Ok(data) => data,
+    if use_cache {
+        if let Some(data) = cache::load(name, &pb) {
+            return data;
+        }
+    }
+    match T::open(pb.clone()) {
+        Ok(data) => {
+            if use_cache {
+                if let Err(err) = cache::store(name, &pb, &data) {
+                    eprintln!("Unable to cache the {} data file. Error: {}", name, err);
+                }
+            }
+            data
+        },
         Err(err) => {
             eprintln!("Error occurred while reading the {} data file.", name);
             eprintln!("Error: {}", err);
@@ -81,11 +287,90 @@ fn get_data<T: FromJsonPath>(pb: PathBuf, name: &'static str, env_name: &'static
 
 }
 
-fn get_datas(treasure_data: PathBuf, item_data: PathBuf) -> (TreasureData, ItemData) {
-    let treasure = get_data(treasure_data, "treasure", "TREASURE");
-    let item = get_data(item_data, "item", "ITEM");
+/// Loads the item data, replacing control characters in the names as described in [`ItemData::sanitize`].
+fn get_item_data(pb: PathBuf, format: ItemFormat, use_cache: bool) -> ItemData {
+    let mut item_data: ItemData = match format {
+        ItemFormat::Json => get_data(pb, "item", "ITEM", use_cache),
+        ItemFormat::GameText => {
+            if !pb.exists() {
+                eprintln!("Missing item data file!");
+                eprintln!("Use the --item-data option or the ITEM_DATA environment variable.");
+                std::process::exit(2);
+            }
+            match File::open(pb).map_err(TreasureError::from).and_then(ItemData::from_game_text) {
+                Ok(data) => data,
+                Err(err) => {
+                    eprintln!("Error occurred while reading the item data file.");
+                    eprintln!("Error: {}", err);
+                    std::process::exit(3);
+                }
+            }
+        }
+    };
+    let sanitized = item_data.sanitize();
+    if sanitized > 0 {
+        eprintln!("Replaced control characters in {} item names with {}.", sanitized, CONTROL_PLACEHOLDER);
+    }
+    item_data
+}
+
+fn get_datas(treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, use_cache: bool) -> (TreasureData, ItemData) {
+    let treasure = get_data(treasure_data, "treasure", "TREASURE", use_cache);
+    let item = get_item_data(item_data, item_format, use_cache);
+    (treasure, item)
+}
+
+/// File names recognized by `--treasure-data-dir` and `--item-data-dir`.
+const TREASURE_DATA_NAMES: [&str; 3] = ["treasure_data.json", "treasures.json", "treasure_data.csv"];
+const ITEM_DATA_NAMES: [&str; 2] = ["item_data.json", "items.json"];
+const GAME_TEXT_NAMES: [&str; 2] = ["item_names.bin", "items.bin"];
+
+/// Finds the single data file in `dir` with one of the conventional `names`.
+fn discover_data(dir: &Path, name: &'static str, names: &[&str]) -> PathBuf {
+    let found = names.iter().map(|file| dir.join(file)).filter(|path| path.is_file()).collect::<Vec<_>>();
+    match found.len() {
+        1 => found.into_iter().next().unwrap(),
+        0 => {
+            eprintln!("No {} data file found in {:?}. Expected one of: {}", name, dir, names.join(", "));
+            std::process::exit(2);
+        },
+        _ => {
+            eprintln!("Found several {} data files in {:?}: {:?}", name, dir, found);
+            eprintln!("Use the --{}-data option to choose one.", name);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// The treasure and item data paths for a dump, discovering them when a data directory was given.
+fn data_paths(opts: &DumpTreasure) -> (PathBuf, PathBuf) {
+    let treasure = match opts.treasure_data_dir.as_ref() {
+        Some(dir) => discover_data(dir, "treasure", &TREASURE_DATA_NAMES),
+        None => opts.treasure_data.clone(),
+    };
+    let item = match opts.item_data_dir.as_ref() {
+        Some(dir) => match opts.item_format {
+            ItemFormat::Json => discover_data(dir, "item", &ITEM_DATA_NAMES),
+            ItemFormat::GameText => discover_data(dir, "item", &GAME_TEXT_NAMES),
+        },
+        None => opts.item_data.clone(),
+    };
     (treasure, item)
 }
+
+fn get_profile(profile: Option<PathBuf>) -> Profile {
+    match profile {
+        Some(pb) => match Profile::open(pb) {
+            Ok(profile) => profile,
+            Err(err) => {
+                eprintln!("Error occurred while reading the profile.");
+                eprintln!("Error: {}", err);
+                std::process::exit(3);
+            }
+        },
+        None => Profile::default(),
+    }
+}
 //
 // enum OutputData<'a> {
 //     Zone(&'a str),
@@ -111,175 +396,286 @@ fn get_datas(treasure_data: PathBuf, item_data: PathBuf) -> (TreasureData, ItemD
 
 enum OutputWriter {
     Stdout(std::io::Stdout),
-    File(File)
+    File(File),
+    /// Collects a file bound for a zip archive, which is added as one entry once it's complete
+    Buffer(Vec<u8>),
 }
 
 impl Write for OutputWriter {
     fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
-        match self { OutputWriter::Stdout(stdout) => stdout.write(buf), OutputWriter::File(file) => file.write(buf) }
+        match self { OutputWriter::Stdout(stdout) => stdout.write(buf), OutputWriter::File(file) => file.write(buf), OutputWriter::Buffer(buffer) => buffer.write(buf) }
     }
 
     fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> IOResult<usize> {
-        match self { OutputWriter::Stdout(stdout) => stdout.write_vectored(bufs), OutputWriter::File(file) => file.write_vectored(bufs) }
+        match self { OutputWriter::Stdout(stdout) => stdout.write_vectored(bufs), OutputWriter::File(file) => file.write_vectored(bufs), OutputWriter::Buffer(buffer) => buffer.write_vectored(bufs) }
     }
 
     fn flush(&mut self) -> IOResult<()> {
-        match self { OutputWriter::Stdout(stdout) => stdout.flush(), OutputWriter::File(file) => file.flush() }
+        match self { OutputWriter::Stdout(stdout) => stdout.flush(), OutputWriter::File(file) => file.flush(), OutputWriter::Buffer(buffer) => buffer.flush() }
     }
 
     fn write_all(&mut self, buf: &[u8]) -> IOResult<()> {
-        match self { OutputWriter::Stdout(stdout) => stdout.write_all(buf), OutputWriter::File(file) => file.write_all(buf) }
+        match self { OutputWriter::Stdout(stdout) => stdout.write_all(buf), OutputWriter::File(file) => file.write_all(buf), OutputWriter::Buffer(buffer) => buffer.write_all(buf) }
     }
 
     fn write_fmt(&mut self, fmt: Arguments<'_>) -> IOResult<()> {
-        match self { OutputWriter::Stdout(stdout) => stdout.write_fmt(fmt), OutputWriter::File(file) => file.write_fmt(fmt) }
+        match self { OutputWriter::Stdout(stdout) => stdout.write_fmt(fmt), OutputWriter::File(file) => file.write_fmt(fmt), OutputWriter::Buffer(buffer) => buffer.write_fmt(fmt) }
     }
 }
 
-pub fn dump_treasure(input: PathBuf, output: Option<PathBuf>, treasure_data: PathBuf, item_data: PathBuf, create_maps: bool) {
-    assert!(!(output.is_some() ^ create_maps));
-    let (treasure_data, item_data) = get_datas(treasure_data, item_data);
+/// Translates `\n` into `\r\n` on the way through when enabled.
+struct LineEndings<W: Write> {
+    inner: W,
+    crlf: bool,
+}
 
-    if !input.exists() {
-        eprintln!("Non-existent input directory: {:?}", input);
-        std::process::exit(4);
+impl<W: Write> LineEndings<W> {
+    fn new(inner: W, crlf: bool) -> LineEndings<W> {
+        LineEndings { inner, crlf }
     }
-    let output = if !output.as_ref().map(|dir| dir.exists()).unwrap_or(true) {
-        let dir = output.unwrap();
-        println!("Non-existent output directory: {:?}. Creating...", &dir);
-        if let Err(err) = std::fs::create_dir(&dir) {
-            eprintln!("Unable to create output directory. Error: {}", err);
-            std::process::exit(4);
+
+    fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for LineEndings<W> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        if !self.crlf {
+            return self.inner.write(buf);
         }
-        Some(dir)
-    } else { output };
+        for (i, line) in buf.split(|b| *b == b'\n').enumerate() {
+            if i > 0 { self.inner.write_all(b"\r\n")?; }
+            self.inner.write_all(line)?;
+        }
+        Ok(buf.len())
+    }
 
-    let iter = WalkDir::new(input)
+    fn flush(&mut self) -> IOResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// A zone's treasures, parsed from its .ebp file.
+pub struct ParsedZone<'a> {
+    pub group: &'a str,
+    pub zone: &'a ZoneData,
+    pub treasures: Vec<ZoneTreasure>,
+}
+
+/// Every .ebp file under `input`, with symlinks resolved. Each directory is walked in file name order,
+/// so the files come out the same way on every run.
+fn zone_files(input: PathBuf) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(input)
         .follow_links(true)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
         .into_iter()
         .filter_map(|a| a.ok())
         .filter(|a| a.file_type().is_file())
         .filter(|a| a.path().extension().map(|a| a == "ebp").unwrap_or(false))
         .map(|it| if it.path_is_symlink() { std::fs::read_link(it.path()) } else { Ok(it.into_path()) })
-        .filter_map(|it| it.ok());
+        .filter_map(|it| it.ok())
+}
 
-    let mut slot_binds = Vec::with_capacity(255);
-    for _ in 0..255 { slot_binds.push(Vec::new()); }
+/// Group members, as `(group, file stem)`, that have no zone data and so will never be dumped.
+fn orphaned_group_entries(treasure_data: &TreasureData) -> Vec<(&str, &str)> {
+    let mut orphans = treasure_data.groups.iter()
+        .flat_map(|(group, stems)| stems.iter().map(move |stem| (group.as_str(), stem.as_str())))
+        .filter(|(_, stem)| !treasure_data.zones.contains_key(*stem))
+        .collect::<Vec<_>>();
+    orphans.sort();
+    orphans
+}
 
-    for path in iter {
-        let file_stem = path.file_stem().unwrap().to_str().unwrap().to_owned();
+/// Group given to zones that aren't listed in any group. Dumps can rename it with `--unknown-group`.
+const UNKNOWN_GROUP: &str = "Unknown";
 
-        let group = if let Some(item) = treasure_data.groups.iter().find(|a| a.1.contains(&file_stem)) {
-            item.0.as_str()
-        } else { "Unknown" };
+/// The group listing `file_stem`. When several do, the first by name, so the choice doesn't vary between runs.
+fn group_of<'a>(treasure_data: &'a TreasureData, file_stem: &str, unknown: &'a str) -> &'a str {
+    if let Some(item) = treasure_data.groups.iter().filter(|a| a.1.contains(file_stem)).min_by_key(|a| a.0) {
+        item.0.as_str()
+    } else { unknown }
+}
 
-        if !treasure_data.zones.contains_key(&file_stem) {
-            continue;
+/// The zone scripts named in a `--files-from` list, or on stdin for `-`. Missing files are skipped with a warning.
+fn listed_files(list: &Path) -> Vec<PathBuf> {
+    let read: IOResult<String> = if list == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text).map(|_| text)
+    } else {
+        std::fs::read_to_string(list)
+    };
+    let text = match read {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Unable to read the file list {:?}. Error: {}", list, err);
+            std::process::exit(4);
         }
-        let zone = &treasure_data.zones[&file_stem];
-
-        if output.is_some() {
-            if let Err(err) = std::fs::DirBuilder::new()
-                .recursive(true)
-                .create(output.as_ref().unwrap().join(group)) {
-                eprintln!("Unable to create file directory. Error: {}", err);
+    };
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| {
+            if !path.is_file() {
+                eprintln!("Warning: Listed file {:?} doesn't exist. Skipping.", path);
             }
+            path.is_file()
+        })
+        .collect()
+}
+
+/// The files under `input` matching the glob `pattern`, exiting if the pattern is invalid.
+fn glob_files(input: &Path, pattern: &str) -> Vec<PathBuf> {
+    // The input directory is matched literally, so brackets or stars in its name don't act as wildcards
+    let full_pattern = PathBuf::from(glob::Pattern::escape(&input.to_string_lossy())).join(pattern);
+    let paths = match glob::glob(&full_pattern.to_string_lossy()) {
+        Ok(paths) => paths,
+        Err(err) => {
+            eprintln!("Invalid input glob '{}'. Error: {}", pattern, err);
+            std::process::exit(4);
         }
+    };
+    let files = paths
+        .filter_map(|path| match path {
+            Ok(path) => Some(path),
+            Err(err) => {
+                eprintln!("Warning: Unable to read {:?} while matching the input glob. Skipping.", err.path());
+                None
+            }
+        })
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    eprintln!("{} files match {:?}.", files.len(), full_pattern);
+    files
+}
 
-        let writer_path = output.as_ref().map(|dir| dir.join(group).join(&zone.name).with_extension("txt"));
-        let mut writer = match writer_path.as_ref() {
-            Some(file_path) => {
-                match File::create(&file_path) { Ok(file) => OutputWriter::File(file), Err(err) => { eprintln!("Error creating file {:?}. Error: {}", file_path, err); continue; }}
-            },
-            None => OutputWriter::Stdout(std::io::stdout())
-        };
+/// Parses every zone under `input` that has zone data, reporting and skipping files that fail.
+pub fn parse_zones(input: PathBuf, treasure_data: &TreasureData) -> Vec<ParsedZone> {
+    if !input.exists() {
+        eprintln!("Non-existent input directory: {:?}", input);
+        std::process::exit(4);
+    }
+    parse_zone_files(zone_files(input), treasure_data)
+}
 
-        let write_res = writeln!(writer, "{}", &zone.name)
-            .and_then(|_| writeln!(writer, "\t{:3}{:6}{:6}{:6}{:6}{:20}{:20}{:20}{:20}{:5}{:>6}{:>6}", "ID", "Slot", "Spn%", "Gil%", "Gil", "Item 1 (%50%)", "Item 2 (50%)", "DA 1 (95%)", "DA 2 (5%)", "DGil", "X", "Y"))
-            .and_then(|_| writeln!(writer, "\t{:=<124}", "="));
-        if let Err(e) = write_res { eprintln!("Error writing to file. {}", e); continue; }
+fn parse_zone_files<I: Iterator<Item = PathBuf>>(files: I, treasure_data: &TreasureData) -> Vec<ParsedZone> {
+    files.filter_map(|path| {
+        let file_stem = path.file_stem()?.to_str()?.to_owned();
+        let zone = treasure_data.zones.get(&file_stem)?;
         let res = File::open(path.as_path()).map_err(|e| TreasureError::from(e))
-            .and_then(|file| read_treasure_files(file, &zone));
-
+            .and_then(|file| read_treasure_files(file, zone));
         match res {
-            Ok(zone_treasures) => {
-                if create_maps {
-                    let svg_path = writer_path.as_ref().unwrap().with_extension("svg");
-                    if let Err(err) = plotter::plot(&svg_path, &zone.name, &zone_treasures) {
-                        eprintln!("Failed to create SVG map for {}. Error: {}", &zone.name, err);
-                    }
-                }
-                // plotter::plot()
-                // plotter::plot(&zone.name, &zone_treasures).expect("creating chart");
-                for treasure in zone_treasures {
-                    let first_item = item_data.ids[&treasure.first_item].as_str();
-                    let second_item = item_data.ids[&treasure.second_item].as_str();
-                    let rare_first_item = item_data.ids[&treasure.rare_first_item].as_str();
-                    let rare_second_item = item_data.ids[&treasure.rare_second_item].as_str();
-                    if treasure.respawn_slot != 255 {
-                        (&mut slot_binds[treasure.respawn_slot as usize]).push((zone.name.clone(), group.to_string(), treasure.id, String::from(first_item)));
-                    }
-                    if let Err(e) = writeln!(writer, "\t{:<3}{:<6x}{:<6}{:<6}{:<6}{:20}{:20}{:20}{:20}{:5}{:6}{:6}", treasure.id, treasure.respawn_slot, treasure.spawn_chance, treasure.gil_chance, treasure.gil_amount, first_item, second_item, rare_first_item, rare_second_item, treasure.rare_gil_amount, treasure.pos_x, treasure.pos_y) {
-                        eprintln!("Error writing to file. {}", e); continue;
-                    }
-                }
-            },
+            Ok(treasures) => Some(ParsedZone { group: group_of(treasure_data, &file_stem, UNKNOWN_GROUP), zone, treasures }),
             Err(err) => {
                 eprintln!("An error occurred while processing file {:?}. Error: {}", path.as_path(), err);
+                None
             }
         }
+    }).collect()
+}
 
-    }
-    let mut slot_out = if let Some(out_dir) = output.as_ref() {
-        let slots = out_dir.join("respawn-slots.txt");
-        match File::create(slots) {
-            Ok(file) => OutputWriter::File(file),
-            Err(err) => { eprintln!("Unable to create respawn-slots.txt.\nError: {}", err); return; }
-        }
-    } else { OutputWriter::Stdout(std::io::stdout()) };
+/// Each treasure's position scaled into 0..1 across the bounding box of the zone's treasures.
+fn normalized_positions(treasures: &[ZoneTreasure]) -> Vec<(f64, f64)> {
+    let x_min = treasures.iter().map(|t| t.pos_x).min().unwrap_or(0) as f64;
+    let x_max = treasures.iter().map(|t| t.pos_x).max().unwrap_or(0) as f64;
+    let y_min = treasures.iter().map(|t| t.pos_y).min().unwrap_or(0) as f64;
+    let y_max = treasures.iter().map(|t| t.pos_y).max().unwrap_or(0) as f64;
+    let normalize = |value: i16, min: f64, max: f64| if max > min { (value as f64 - min) / (max - min) } else { 0.0 };
+    treasures.iter()
+        .map(|t| (normalize(t.pos_x, x_min, x_max), normalize(t.pos_y, y_min, y_max)))
+        .collect()
+}
 
-    writeln!(slot_out, "Slot => [(Zone: Area :: ID = Item), (...), ...]").expect("Writing respawn-slots.txt");
-    for (i, mut slot) in slot_binds.drain(..).enumerate() {
+/// Size of each treasure record in a zone script.
+pub const RECORD_SIZE: usize = 24;
 
-        write!(slot_out, "{:02x} => [", i).expect("Writing respawn-slots.txt");
-        let num_in_slot = slot.len();
-        if num_in_slot > 0 {
-            for (k, data) in slot.drain(..).enumerate() {
-                write!(slot_out, "({}: {} :: {} = {}){}", data.1, data.0, data.2, data.3, if k == num_in_slot - 1 { "" } else { ", " }).expect("Writing respawn-slots.txt");
-            }
-        }
-        writeln!(slot_out, "]").expect("Writing respawn-slots.txt");
-    }
+/// Bytes taken by each field of a record, in order. The fifth is skipped when parsing; its meaning is unknown.
+const RECORD_LAYOUT: [usize; 13] = [4, 2, 2, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2];
 
+pub fn read_treasure_files<R: Read + Seek>(reader: R, data: &ZoneData) -> Result<Vec<ZoneTreasure>, TreasureError> {
+    read_raw_records(reader, data)?.iter().map(parse_record).collect()
 }
 
-fn read_treasure_files<R: Read + Seek>(reader: R, data: &ZoneData) -> Result<Vec<ZoneTreasure>, TreasureError> {
+/// The zone's treasure records, unparsed.
+fn read_raw_records<R: Read + Seek>(reader: R, data: &ZoneData) -> Result<Vec<[u8; RECORD_SIZE]>, TreasureError> {
     let mut reader = reader;
     reader.seek(SeekFrom::Start(data.offset))?;
-    let mut buffer = [0u8; 24];
-
-    let mut treasures = Vec::with_capacity(data.quantity as usize);
-
-    for _ in 0..data.quantity {
-        reader.read_exact(&mut buffer)?;
-
-        let mut cursor = Cursor::new(&mut buffer);
-
-        treasures.push(ZoneTreasure {
-            id: cursor.read_u32::<LE>()?,
-            pos_x: cursor.read_i16::<LE>()?,
-            pos_y: cursor.read_i16::<LE>()?,
-            respawn_slot: {cursor.read_u8()?; cursor.read_u8()?},
-            spawn_chance: cursor.read_u8()?,
-            gil_chance: cursor.read_u8()?,
-            first_item: cursor.read_u16::<LE>()?,
-            second_item: cursor.read_u16::<LE>()?,
-            rare_first_item: cursor.read_u16::<LE>()?,
-            rare_second_item: cursor.read_u16::<LE>()?,
-            gil_amount: cursor.read_u16::<LE>()?,
-            rare_gil_amount: cursor.read_u16::<LE>()?,
-        });
+
+    // The whole table is read at once rather than a record at a time, then split up in memory.
+    let mut table = vec![0u8; data.quantity as usize * RECORD_SIZE];
+    reader.read_exact(&mut table)?;
+
+    Ok(table.chunks_exact(RECORD_SIZE).map(|chunk| {
+        let mut record = [0u8; RECORD_SIZE];
+        record.copy_from_slice(chunk);
+        record
+    }).collect())
+}
+
+pub fn parse_record(record: &[u8; RECORD_SIZE]) -> Result<ZoneTreasure, TreasureError> {
+    let mut cursor = Cursor::new(&record[..]);
+
+    Ok(ZoneTreasure {
+        id: cursor.read_u32::<LE>()?,
+        pos_x: cursor.read_i16::<LE>()?,
+        pos_y: cursor.read_i16::<LE>()?,
+        respawn_slot: {cursor.read_u8()?; cursor.read_u8()?},
+        spawn_chance: cursor.read_u8()?,
+        gil_chance: cursor.read_u8()?,
+        first_item: cursor.read_u16::<LE>()?,
+        second_item: cursor.read_u16::<LE>()?,
+        rare_first_item: cursor.read_u16::<LE>()?,
+        rare_second_item: cursor.read_u16::<LE>()?,
+        gil_amount: cursor.read_u16::<LE>()?,
+        rare_gil_amount: cursor.read_u16::<LE>()?,
+    })
+}
+
+/// The record as hex, with a space between each field so the layout is visible.
+fn record_hex(record: &[u8; RECORD_SIZE]) -> String {
+    let mut start = 0;
+    RECORD_LAYOUT.iter().map(|len| {
+        let field = hex::encode(&record[start..start + len]);
+        start += len;
+        field
+    }).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::treasure::{csv_field, csv_fields, parse_record, read_treasure_files, ZoneData, RECORD_SIZE};
+
+    /// Reading the table in one go has to decode the same treasures as reading it a record at a time.
+    #[test]
+    fn bulk_read_test() {
+        let mut script = vec![0u8; 6 + 5 * RECORD_SIZE + 10];
+        // Seeded, so every run checks the same bytes
+        StdRng::seed_from_u64(173).fill(&mut script[..]);
+        let zone = ZoneData { name: String::from("Test Zone"), offset: 6, quantity: 5, bounds: None, world_offset: None };
+
+        let bulk = read_treasure_files(Cursor::new(script.clone()), &zone).expect("reading treasures");
+
+        let mut cursor = Cursor::new(&script[6..]);
+        let per_record = (0..5).map(|_| {
+            let mut record = [0u8; RECORD_SIZE];
+            cursor.read_exact(&mut record).expect("reading record");
+            parse_record(&record).expect("parsing record")
+        }).collect::<Vec<_>>();
+
+        assert_eq!(bulk, per_record);
     }
 
-    Ok(treasures)
+    #[test]
+    fn csv_field_test() {
+        let names = ["Potion", "Hi-Potion, Large", "The \"Best\" Sword"];
+        let line = names.iter().map(|name| csv_field(name)).collect::<Vec<_>>().join(",");
+        assert_eq!(line, r#"Potion,"Hi-Potion, Large","The ""Best"" Sword""#);
+        assert_eq!(csv_fields(&line), names);
+    }
 }
@@ -0,0 +1,208 @@
+use std::io::Write;
+
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::error::TreasureError;
+use crate::treasure::{ItemData, ZoneTreasure};
+use crate::treasure::plotter::PlotOptions;
+
+/// Builds a GeoJSON point for the treasure. Positions go through the same transform as the maps,
+/// with the Y axis negated unless `flip_y` is set so the layout matches the SVG output.
+pub fn geojson_feature(treasure: &ZoneTreasure, items: [String; 4], opts: &PlotOptions) -> Value {
+    let (x, y) = opts.transform(treasure);
+    let [first_item, second_item, rare_first_item, rare_second_item] = items;
+    json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "Point",
+            "coordinates": [x, if opts.flip_y { y } else { -y }],
+        },
+        "properties": {
+            "id": treasure.id,
+            "respawn_slot": treasure.respawn_slot,
+            "spawn_chance": treasure.spawn_chance,
+            "gil_chance": treasure.gil_chance,
+            "gil_amount": treasure.gil_amount,
+            "rare_gil_amount": treasure.rare_gil_amount,
+            "first_item": first_item,
+            "second_item": second_item,
+            "rare_first_item": rare_first_item,
+            "rare_second_item": rare_second_item,
+        },
+    })
+}
+
+/// Describes the treasure with each item as `{ "id", "name" }`, so nothing has to be mapped back from names.
+/// `name` is null when the item data doesn't know the ID. Gil carries `rare_amount` only when it differs from `amount`.
+pub fn json_treasure(treasure: &ZoneTreasure, items: [String; 4], item_data: &ItemData) -> Value {
+    let ids = treasure.all_items();
+    let [first_item, second_item, rare_first_item, rare_second_item] = items;
+    let item = |id: u16, name: String| json!({ "id": id, "name": if item_data.name(id).is_some() { Some(name) } else { None } });
+    let mut gil = json!({ "amount": treasure.gil_amount });
+    if treasure.rare_gil_amount != treasure.gil_amount {
+        gil["rare_amount"] = treasure.rare_gil_amount.into();
+    }
+    json!({
+        "id": treasure.id,
+        "pos_x": treasure.pos_x,
+        "pos_y": treasure.pos_y,
+        "respawn_slot": treasure.respawn_slot,
+        "spawn_chance": treasure.spawn_chance,
+        "gil_chance": treasure.gil_chance,
+        "gil": gil,
+        "first_item": item(ids[0], first_item),
+        "second_item": item(ids[1], second_item),
+        "rare_first_item": item(ids[2], rare_first_item),
+        "rare_second_item": item(ids[3], rare_second_item),
+    })
+}
+
+pub fn write_json<W: Write>(writer: &mut W, group: &str, zone: &str, treasures: Vec<Value>) -> Result<(), TreasureError> {
+    let zone = json!({
+        "group": group,
+        "name": zone,
+        "treasures": treasures,
+    });
+    serde_json::to_writer(&mut *writer, &zone)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Like [`write_json`], but with every object's keys sorted whatever map serde_json was built with,
+/// so the same zone always serializes to the same bytes.
+pub fn write_canonical_json<W: Write>(writer: &mut W, group: &str, zone: &str, treasures: Vec<Value>) -> Result<(), TreasureError> {
+    let zone = json!({
+        "group": group,
+        "name": zone,
+        "treasures": treasures,
+    });
+    serde_json::to_writer(&mut *writer, &Canonical(&zone))?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Serializes a JSON value with its objects' keys in sorted order.
+struct Canonical<'a>(&'a Value);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Object(map) => serializer.collect_map(map.iter().map(|(k, v)| (k, Canonical(v))).collect::<BTreeMap<_, _>>()),
+            Value::Array(values) => serializer.collect_seq(values.iter().map(Canonical)),
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+pub fn write_geojson<W: Write>(writer: &mut W, zone: &str, features: Vec<Value>) -> Result<(), TreasureError> {
+    let collection = json!({
+        "type": "FeatureCollection",
+        "name": zone,
+        "features": features,
+    });
+    serde_json::to_writer(&mut *writer, &collection)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes the zone as a Lua script, `return { ["<zone>"] = { <treasure>, ... } }`, for `require`-ing from Lua.
+/// The treasures are the same values [`write_json`] takes, converted to table constructors.
+pub fn write_lua<W: Write>(writer: &mut W, zone: &str, treasures: Vec<Value>) -> Result<(), TreasureError> {
+    let mut lua = String::new();
+    lua.push_str("return {\n\t[");
+    lua_string(&mut lua, zone);
+    lua.push_str("] = {\n");
+    for treasure in &treasures {
+        lua.push_str("\t\t");
+        lua_value(&mut lua, treasure);
+        lua.push_str(",\n");
+    }
+    lua.push_str("\t},\n}\n");
+    writer.write_all(lua.as_bytes())?;
+    Ok(())
+}
+
+fn lua_value(lua: &mut String, value: &Value) {
+    match value {
+        Value::Null => lua.push_str("nil"),
+        Value::Bool(b) => lua.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => lua.push_str(&n.to_string()),
+        Value::String(s) => lua_string(lua, s),
+        Value::Array(values) => {
+            lua.push('{');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 { lua.push_str(", "); }
+                lua_value(lua, value);
+            }
+            lua.push('}');
+        },
+        Value::Object(map) => {
+            lua.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 { lua.push_str(", "); }
+                if is_lua_name(key) {
+                    lua.push_str(key);
+                } else {
+                    lua.push('[');
+                    lua_string(lua, key);
+                    lua.push(']');
+                }
+                lua.push_str(" = ");
+                lua_value(lua, value);
+            }
+            lua.push('}');
+        },
+    }
+}
+
+/// Quotes `s` as a Lua string literal. Control characters are written as decimal escapes, which unlike
+/// Lua's \x escapes work in every version.
+fn lua_string(lua: &mut String, s: &str) {
+    lua.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => lua.push_str("\\\""),
+            '\\' => lua.push_str("\\\\"),
+            '\n' => lua.push_str("\\n"),
+            '\r' => lua.push_str("\\r"),
+            '\t' => lua.push_str("\\t"),
+            c if c.is_control() && (c as u32) < 0x80 => lua.push_str(&format!("\\{:03}", c as u32)),
+            c => lua.push(c),
+        }
+    }
+    lua.push('"');
+}
+
+const LUA_KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+/// Whether `key` can be written as a bare field name rather than `["key"]`.
+fn is_lua_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !LUA_KEYWORDS.contains(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{lua_string, lua_value};
+
+    #[test]
+    fn lua_value_test() {
+        let mut lua = String::new();
+        lua_string(&mut lua, "Say \"hi\"\\\n\u{1}é");
+        assert_eq!(lua, r#""Say \"hi\"\\\n\001é""#);
+
+        let mut lua = String::new();
+        lua_value(&mut lua, &json!({ "end": [1.5, true], "id": 3, "name": null, "two words": "x" }));
+        assert_eq!(lua, r#"{["end"] = {1.5, true}, id = 3, name = nil, ["two words"] = "x"}"#);
+    }
+}
@@ -1,40 +1,520 @@
+use plotters::drawing::DrawingAreaErrorKind;
 use plotters::prelude::*;
 
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
 
-use crate::treasure::ZoneTreasure;
+use crate::opt::{MarkerShape, RouteMode};
+use crate::treasure::{Bounds, ItemData, ZoneTreasure, normalized_positions};
 
-pub fn plot<P: AsRef<Path>, S: AsRef<str>>(output_path: &P, name: &S, zone_data: &Vec<ZoneTreasure>) -> Result<(), Box<dyn std::error::Error>> {
-    let name = name.as_ref();
-    let x_max = zone_data.iter().map(|a| a.pos_x).max().unwrap();
-    let y_max = zone_data.iter().map(|a| a.pos_y).max().unwrap();
-    let x_min = zone_data.iter().map(|a| a.pos_x).min().unwrap();
-    let y_min = zone_data.iter().map(|a| a.pos_y).min().unwrap();
+/// Canvas extent used for maps drawn with relative positions.
+const RELATIVE_EXTENT: f64 = 1000.0;
+/// Widest or tallest a map may be, in pixels. Anything larger comes from positions or a scale gone wrong.
+const MAX_CANVAS_SIZE: i64 = 100_000;
 
-    let x_dif = (x_max - x_min) as u32;
-    let y_dif = (y_max - y_min) as u32;
+/// Why a map couldn't be drawn.
+#[derive(Debug)]
+pub enum PlotError {
+    /// The positions couldn't be placed on a canvas, as when there are none or they span too far
+    Coordinates(String),
+    /// The plotting library failed to draw the map
+    Render(String),
+    /// The map couldn't be written out
+    IO(std::io::Error),
+    /// The plotting library panicked partway through the map
+    Panic(String),
+}
+
+impl Display for PlotError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Self::Coordinates(msg) => write!(f, "The positions couldn't be placed: {}", msg),
+            Self::Render(msg) => write!(f, "Drawing failed: {}", msg),
+            Self::IO(io) => write!(f, "An IO error occurred: {}", io),
+            Self::Panic(msg) => write!(f, "The plotting library panicked: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+impl From<std::io::Error> for PlotError {
+    fn from(io: std::io::Error) -> Self { PlotError::IO(io) }
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for PlotError {
+    fn from(err: DrawingAreaErrorKind<E>) -> Self { PlotError::Render(err.to_string()) }
+}
+
+/// Runs `draw`, turning a panic inside it into [`PlotError::Panic`] so one bad zone can't end the whole dump.
+fn catch_panic<T, F: FnOnce() -> Result<T, PlotError>>(draw: F) -> Result<T, PlotError> {
+    match std::panic::catch_unwind(AssertUnwindSafe(draw)) {
+        Ok(res) => res,
+        Err(payload) => {
+            let msg = match payload.downcast_ref::<&str>() {
+                Some(msg) => msg.to_string(),
+                None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| String::from("unknown cause")),
+            };
+            Err(PlotError::Panic(msg))
+        }
+    }
+}
+
+/// The canvas size covering x_min..x_max and y_min..y_max, without the margins.
+fn canvas_size((x_min, x_max): (i32, i32), (y_min, y_max): (i32, i32)) -> Result<(u32, u32), PlotError> {
+    let (x_dif, y_dif) = (x_max as i64 - x_min as i64, y_max as i64 - y_min as i64);
+    if x_dif > MAX_CANVAS_SIZE || y_dif > MAX_CANVAS_SIZE {
+        return Err(PlotError::Coordinates(format!("the map would be {}x{} pixels, more than the {} allowed", x_dif, y_dif, MAX_CANVAS_SIZE)));
+    }
+    Ok((x_dif as u32, y_dif as u32))
+}
+
+/// Controls how world positions are mapped onto the map canvas.
+#[derive(Clone, Debug)]
+pub struct PlotOptions {
+    /// Draw larger Y positions towards the top of the map rather than the bottom
+    pub flip_y: bool,
+    pub scale: f64,
+    pub offset: (f64, f64),
+    /// Stretch each zone's treasures across a fixed size canvas, ignoring scale and offset
+    pub relative: bool,
+    /// Shape of each treasure marker. Treasures whose Diamond Armlet slots are rarer than their
+    /// common slots use the alternate shape instead.
+    pub marker: MarkerShape,
+    pub marker_size: u32,
+    /// Item IDs whose chests are ringed on the map
+    pub highlight: HashSet<u16>,
+    pub route: Option<RouteMode>,
+    /// Badge markers that share their position with other chests with the number of chests there, as ×N
+    pub shared_badges: bool,
+    /// Clip zone maps to this area of world positions, leaving off the chests outside it
+    pub map_region: Option<Bounds>,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions { flip_y: false, scale: 1.0, offset: (0.0, 0.0), relative: false, marker: MarkerShape::Circle, marker_size: 5, highlight: HashSet::new(), route: None, shared_badges: false, map_region: None }
+    }
+}
+
+impl PlotOptions {
+    pub fn transform(&self, treasure: &ZoneTreasure) -> (i32, i32) {
+        self.transform_point(treasure.pos_x, treasure.pos_y)
+    }
+
+    fn transform_point(&self, pos_x: i16, pos_y: i16) -> (i32, i32) {
+        self.transform_world(pos_x as i32, pos_y as i32)
+    }
 
-    let root = SVGBackend::new(output_path.as_ref(), (x_dif + 200, y_dif + 200)).into_drawing_area();
-    root.fill(&WHITE)?;
-    let root = root.margin(10, 10, 10, 10);
+    /// Like [`transform_point`], for positions already moved into a region's world space.
+    fn transform_world(&self, x: i32, y: i32) -> (i32, i32) {
+        let x = x as f64 * self.scale + self.offset.0;
+        let y = y as f64 * self.scale + self.offset.1;
+        (x.round() as i32, y.round() as i32)
+    }
+
+    /// Whether the treasure can drop any of the `highlight` items.
+    pub fn highlighted(&self, treasure: &ZoneTreasure) -> bool {
+        treasure.all_items().iter().any(|id| self.highlight.contains(id))
+    }
+}
+
+/// Writes the zone map rendered by [`render_svg`] to `output_path`.
+pub fn plot<P: AsRef<Path>>(output_path: &P, zone_name: &str, treasures: &[ZoneTreasure], bounds: Option<Bounds>, item_data: &ItemData, opts: &PlotOptions) -> Result<(), PlotError> {
+    let svg = render_svg(zone_name, treasures, bounds, item_data, opts)?;
+    std::fs::write(output_path, svg)?;
+    Ok(())
+}
+
+/// Renders the zone map to an SVG document without touching the disk.
+/// Item data is only used to color and shape markers by rarity, and to fill in tooltips.
+/// When the zone's bounds are known the canvas covers them, growing to fit any treasure outside.
+pub fn render_svg(zone_name: &str, treasures: &[ZoneTreasure], bounds: Option<Bounds>, item_data: &ItemData, opts: &PlotOptions) -> Result<String, PlotError> {
+    let mut svg = String::new();
+    // Relative positions are no longer world positions, so there's nothing to clip them against.
+    catch_panic(|| match opts.map_region.filter(|_| !opts.relative) {
+        Some(region) => {
+            let inside = treasures.iter().copied().filter(|t| region.contains(t)).collect::<Vec<_>>();
+            draw(&mut svg, zone_name, &inside, Some(region), item_data, opts, treasures.len() - inside.len())
+        },
+        None => draw(&mut svg, zone_name, treasures, bounds, item_data, opts, 0),
+    })?;
+    Ok(svg)
+}
+
+struct Marker {
+    pos: (i32, i32),
+    id: u32,
+    tier: Option<u8>,
+    shape: MarkerShape,
+    size: u32,
+    highlighted: bool,
+}
+
+const TIER_COLORS: [RGBColor; 5] = [
+    RGBColor(128, 128, 128),
+    RGBColor(30, 160, 30),
+    RGBColor(30, 90, 220),
+    RGBColor(160, 40, 200),
+    RGBColor(230, 140, 0),
+];
+
+fn tier_color(tier: Option<u8>) -> RGBColor {
+    match tier {
+        Some(tier) => TIER_COLORS[std::cmp::min(tier as usize, TIER_COLORS.len() - 1)],
+        None => RED,
+    }
+}
+
+/// `clipped` is the number of chests left off for being outside `--map-region`, noted in the corner when there are any.
+fn draw(svg: &mut String, name: &str, zone_data: &[ZoneTreasure], bounds: Option<Bounds>, item_data: &ItemData, opts: &PlotOptions, clipped: usize) -> Result<(), PlotError> {
+    let relative = if opts.relative { normalized_positions(zone_data) } else { Vec::new() };
+    let markers = zone_data.iter().enumerate().map(|(i, a)| {
+        let common = item_data.rarity_of(&a.common_items());
+        let rare = item_data.rarity_of(&a.rare_items());
+        let pos = match relative.get(i) {
+            Some((x, y)) => ((x * RELATIVE_EXTENT).round() as i32, (y * RELATIVE_EXTENT).round() as i32),
+            None => opts.transform(a),
+        };
+        let upgraded = rare > common;
+        let (shape, size) = if upgraded { (opts.marker.alternate(), opts.marker_size + 2) } else { (opts.marker, opts.marker_size) };
+        Marker { pos, id: a.id, tier: std::cmp::max(common, rare), shape, size, highlighted: opts.highlighted(a) }
+    }).collect::<Vec<_>>();
+    // Bounds are in world coordinates, which relative positions no longer are.
+    let corners = match bounds {
+        Some(bounds) if !opts.relative => vec![opts.transform_point(bounds.min_x, bounds.min_y), opts.transform_point(bounds.max_x, bounds.max_y)],
+        _ => Vec::new(),
+    };
+    let extent = || markers.iter().map(|a| a.pos).chain(corners.iter().copied());
+    let x_max = extent().map(|a| a.0).max().ok_or_else(|| PlotError::Coordinates(String::from("there are no treasures to place")))?;
+    let y_max = extent().map(|a| a.1).max().unwrap();
+    let x_min = extent().map(|a| a.0).min().unwrap();
+    let y_min = extent().map(|a| a.1).min().unwrap();
+
+    let (x_dif, y_dif) = canvas_size((x_min, x_max), (y_min, y_max))?;
+
+    let canvas = SVGBackend::with_string(svg, (x_dif + 200, y_dif + 200)).into_drawing_area();
+    canvas.fill(&WHITE)?;
+    if clipped > 0 {
+        canvas.draw(&Text::new(format!("{} chests outside the region", clipped), (5, 5), ("sans-serif", 14).into_font()))?;
+    }
+    let root = canvas.margin(10, 10, 10, 10);
     let mut chart = ChartBuilder::on(&root)
         .caption(name, ("sans-serif", 40).into_font())
         .x_label_area_size(20)
         .y_label_area_size(40)
-        .build_ranged(x_min as i32..x_max as i32, 0..y_dif as i32)?;
+        .build_ranged(x_min..x_max, 0..y_dif as i32)?;
 
-    chart.draw_series(PointSeries::of_element(
-        zone_data, 5,&RED, &|c, s, st| {
-            let pos = (c.pos_x as i32, y_max as i32 - c.pos_y as i32);
-            EmptyElement::at(pos) + Circle::new((0, 0), s, st.filled())
-            + Text::new(format!("{}", c.id + 1), (0, 0), ("sans-serif", 16).into_font())
-        }))?;
+    let to_chart = |(x, y): (i32, i32)| (x, if opts.flip_y { y - y_min } else { y_max - y });
+
+    let label = |m: &Marker| Text::new(format!("{}", m.id + 1), (0, 0), ("sans-serif", 16).into_font());
+
+    if let Some(mode) = opts.route {
+        let points = markers.iter().map(|m| m.pos).collect::<Vec<_>>();
+        let order = match mode {
+            RouteMode::Nearest => nearest_route(&points),
+            RouteMode::Optimized => optimized_route(&points),
+        };
+        let route = order.into_iter().map(|i| to_chart(points[i])).collect::<Vec<_>>();
+        chart.draw_series(std::iter::once(PathElement::new(route, &BLUE)))?;
+    }
 
+    // Each shape is a different element type, so every shape gets its own series.
+    chart.draw_series(markers.iter().filter(|m| m.shape == MarkerShape::Circle).map(|m| {
+        EmptyElement::at(to_chart(m.pos)) + Circle::new((0, 0), m.size, tier_color(m.tier).filled())
+        + label(m)
+    }))?;
+    chart.draw_series(markers.iter().filter(|m| m.shape == MarkerShape::Square).map(|m| {
+        let s = m.size as i32;
+        EmptyElement::at(to_chart(m.pos)) + Rectangle::new([(-s, -s), (s, s)], tier_color(m.tier).filled())
+        + label(m)
+    }))?;
+    chart.draw_series(markers.iter().filter(|m| m.shape == MarkerShape::Diamond).map(|m| {
+        let s = m.size as i32;
+        EmptyElement::at(to_chart(m.pos)) + Polygon::new(vec![(0, -s), (s, 0), (0, s), (-s, 0)], tier_color(m.tier).filled())
+        + label(m)
+    }))?;
+    chart.draw_series(markers.iter().filter(|m| m.shape == MarkerShape::Triangle).map(|m| {
+        EmptyElement::at(to_chart(m.pos)) + TriangleMarker::new((0, 0), m.size, tier_color(m.tier).filled())
+        + label(m)
+    }))?;
+    chart.draw_series(markers.iter().filter(|m| m.highlighted).map(|m| {
+        Circle::new(to_chart(m.pos), m.size + 4, &BLACK)
+    }))?;
+    if opts.shared_badges {
+        let mut shared: BTreeMap<(i32, i32), (usize, u32)> = BTreeMap::new();
+        for m in &markers {
+            let entry = shared.entry(m.pos).or_insert((0, m.size));
+            entry.0 += 1;
+        }
+        chart.draw_series(shared.into_iter().filter(|(_, (count, _))| *count > 1).map(|(pos, (count, size))| {
+            let s = size as i32;
+            EmptyElement::at(to_chart(pos)) + Text::new(format!("\u{d7}{}", count), (s + 2, -s - 14), ("sans-serif", 14).into_font())
+        }))?;
+    }
 
+    let hitboxes = markers.iter().map(|m| (chart.backend_coord(&to_chart(m.pos)), m.size)).collect::<Vec<_>>();
+    // The backend only finishes writing the document once it's dropped.
+    drop(chart);
+    drop(root);
+    drop(canvas);
 
+    // Plotters has no way to attach a <title> to an element, so transparent hitboxes carrying the
+    // tooltips are laid over the markers instead.
+    let mut overlay = String::from("<g class=\"tooltips\">\n");
+    for (((x, y), size), treasure) in hitboxes.into_iter().zip(zone_data.iter()) {
+        let s = size as i32 + 2;
+        overlay.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\" fill-opacity=\"0\"><title>{}</title></rect>\n",
+            x - s, y - s, 2 * s, 2 * s, escape_html(&tooltip(treasure, item_data))));
+    }
+    overlay.push_str("</g>\n");
+    match svg.rfind("</svg>") {
+        Some(end) => svg.insert_str(end, &overlay),
+        None => svg.push_str(&overlay),
+    }
 
     Ok(())
+}
+
+/// One zone of a region map, placed in the region by its world offset.
+#[derive(Clone, Debug)]
+pub struct RegionZone {
+    pub name: String,
+    pub bounds: Option<Bounds>,
+    pub world_offset: (i32, i32),
+    pub treasures: Vec<ZoneTreasure>,
+}
+
+/// Renders every zone of a region onto one canvas, each moved by its world offset and outlined by
+/// its bounds, or by the extent of its treasures when the bounds aren't known. Relative positions
+/// don't apply, and markers are plain circles colored by rarity.
+pub fn render_region_svg(region: &str, zones: &[RegionZone], item_data: &ItemData, opts: &PlotOptions) -> Result<String, PlotError> {
+    catch_panic(|| draw_region(region, zones, item_data, opts))
+}
+
+fn draw_region(region: &str, zones: &[RegionZone], item_data: &ItemData, opts: &PlotOptions) -> Result<String, PlotError> {
+    let world = |zone: &RegionZone, x: i16, y: i16| opts.transform_world(x as i32 + zone.world_offset.0, y as i32 + zone.world_offset.1);
+    let mut markers = Vec::new();
+    let mut outlines = Vec::new();
+    for zone in zones {
+        let points = zone.treasures.iter().map(|t| world(zone, t.pos_x, t.pos_y)).collect::<Vec<_>>();
+        let corners = match zone.bounds {
+            Some(bounds) => Some((world(zone, bounds.min_x, bounds.min_y), world(zone, bounds.max_x, bounds.max_y))),
+            None if points.is_empty() => None,
+            None => Some((
+                (points.iter().map(|p| p.0).min().unwrap(), points.iter().map(|p| p.1).min().unwrap()),
+                (points.iter().map(|p| p.0).max().unwrap(), points.iter().map(|p| p.1).max().unwrap()),
+            )),
+        };
+        if let Some(corners) = corners {
+            outlines.push((zone.name.as_str(), corners));
+        }
+        markers.extend(zone.treasures.iter().zip(points).map(|(t, pos)| (pos, t.id, item_data.rarity_of(&t.all_items()))));
+    }
+
+    let extent = || markers.iter().map(|m| m.0).chain(outlines.iter().flat_map(|(_, (a, b))| vec![*a, *b]));
+    let x_min = extent().map(|a| a.0).min().ok_or_else(|| PlotError::Coordinates(String::from("there are no zones to place")))?;
+    let x_max = extent().map(|a| a.0).max().unwrap();
+    let (y_min, y_max) = (extent().map(|a| a.1).min().unwrap(), extent().map(|a| a.1).max().unwrap());
+    let (x_dif, y_dif) = canvas_size((x_min, x_max), (y_min, y_max))?;
+
+    let mut svg = String::new();
+    {
+        let canvas = SVGBackend::with_string(&mut svg, (x_dif + 200, y_dif + 200)).into_drawing_area();
+        canvas.fill(&WHITE)?;
+        let root = canvas.margin(10, 10, 10, 10);
+        let mut chart = ChartBuilder::on(&root)
+            .caption(region, ("sans-serif", 40).into_font())
+            .x_label_area_size(20)
+            .y_label_area_size(40)
+            .build_ranged(x_min..x_max, 0..y_dif as i32)?;
+        let to_chart = |(x, y): (i32, i32)| (x, if opts.flip_y { y - y_min } else { y_max - y });
+
+        chart.draw_series(outlines.iter().map(|(_, (a, b))| {
+            let path = vec![(a.0, a.1), (b.0, a.1), (b.0, b.1), (a.0, b.1), (a.0, a.1)];
+            PathElement::new(path.into_iter().map(to_chart).collect::<Vec<_>>(), &BLACK)
+        }))?;
+        chart.draw_series(outlines.iter().map(|(name, (a, b))| {
+            // Label the corner drawn top left, whichever way up the map is.
+            let top = if opts.flip_y { std::cmp::min(a.1, b.1) } else { std::cmp::max(a.1, b.1) };
+            Text::new(name.to_string(), to_chart((std::cmp::min(a.0, b.0), top)), ("sans-serif", 20).into_font())
+        }))?;
+        chart.draw_series(markers.iter().map(|(pos, id, tier)| {
+            EmptyElement::at(to_chart(*pos)) + Circle::new((0, 0), opts.marker_size, tier_color(*tier).filled())
+            + Text::new(format!("{}", id + 1), (0, 0), ("sans-serif", 16).into_font())
+        }))?;
+    }
+    Ok(svg)
+}
+
+/// Visits every point once, starting from the first and always moving to the closest unvisited point.
+fn nearest_route(points: &[(i32, i32)]) -> Vec<usize> {
+    let distance = |a: (i32, i32), b: (i32, i32)| {
+        let (dx, dy) = ((a.0 - b.0) as i64, (a.1 - b.1) as i64);
+        dx * dx + dy * dy
+    };
+    let mut remaining = (1..points.len()).collect::<Vec<_>>();
+    let mut route = Vec::with_capacity(points.len());
+    if !points.is_empty() {
+        route.push(0);
+    }
+    while !remaining.is_empty() {
+        let last = points[*route.last().unwrap()];
+        let (closest, _) = remaining.iter().enumerate().min_by_key(|(_, i)| distance(last, points[**i])).unwrap();
+        route.push(remaining.remove(closest));
+    }
+    route
+}
+
+/// Improves the nearest neighbour route with 2-opt: reversing any stretch of it that makes the route shorter,
+/// until none does. The route still starts from the first point, and doesn't return to it.
+pub(super) fn optimized_route(points: &[(i32, i32)]) -> Vec<usize> {
+    let between = |a: usize, b: usize| distance(points[a], points[b]);
+    let mut route = nearest_route(points);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..route.len() {
+            for j in i + 1..route.len() {
+                // Reversing route[i..=j] swaps the edges into i and out of j
+                let before = between(route[i - 1], route[i]) + route.get(j + 1).map(|&next| between(route[j], next)).unwrap_or(0.0);
+                let after = between(route[i - 1], route[j]) + route.get(j + 1).map(|&next| between(route[i], next)).unwrap_or(0.0);
+                if after + 1e-9 < before {
+                    route[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+    route
+}
+
+/// The distance covered walking the points in the order of `route`.
+pub(super) fn route_length(points: &[(i32, i32)], route: &[usize]) -> f64 {
+    route.windows(2).map(|pair| distance(points[pair[0]], points[pair[1]])).sum()
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> f64 {
+    ((a.0 - b.0) as f64).hypot((a.1 - b.1) as f64)
+}
+
+fn tooltip(treasure: &ZoneTreasure, item_data: &ItemData) -> String {
+    let name = |id: u16| match item_data.name(id) {
+        Some(name) => name.clone(),
+        None => format!("Unknown 0x{:04x}", id),
+    };
+    format!("Chest {}\nSpawn {}%, gil {}% ({}, Diamond Armlet {})\nItems: {}, {}\nDiamond Armlet: {}, {}",
+            treasure.id + 1, treasure.spawn_chance, treasure.gil_chance, treasure.gil_amount, treasure.rare_gil_amount,
+            name(treasure.first_item), name(treasure.second_item), name(treasure.rare_first_item), name(treasure.rare_second_item))
+}
+
+/// Writes a single HTML document with one titled section per rendered map.
+/// Each entry is `(group, zone name, svg markup)`.
+pub fn write_html<W: Write>(writer: &mut W, maps: &[(String, String, String)]) -> std::io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html>")?;
+    writeln!(writer, "<head><meta charset=\"utf-8\"><title>Treasure Maps</title></head>")?;
+    writeln!(writer, "<body>")?;
+    for (group, zone, svg) in maps {
+        writeln!(writer, "<section>")?;
+        writeln!(writer, "<h2>{}: {}</h2>", escape_html(group), escape_html(zone))?;
+        writer.write_all(svg.as_bytes())?;
+        writeln!(writer)?;
+        writeln!(writer, "</section>")?;
+    }
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::treasure::{Bounds, ItemData, ZoneTreasure};
+    use crate::treasure::plotter::{catch_panic, nearest_route, optimized_route, render_region_svg, render_svg, route_length, PlotError, PlotOptions, RegionZone};
+
+    fn treasure(id: u32, pos_x: i16, pos_y: i16) -> ZoneTreasure {
+        ZoneTreasure {
+            id, pos_x, pos_y,
+            respawn_slot: 255, spawn_chance: 100, gil_chance: 50,
+            first_item: 0, second_item: 1, rare_first_item: 2, rare_second_item: 3,
+            gil_amount: 100, rare_gil_amount: 1000,
+        }
+    }
+
+    #[test]
+    fn render_svg_test() {
+        let treasures = vec![treasure(0, 0, 0), treasure(1, 120, 40), treasure(2, -60, 300)];
+        let item_data = ItemData { ids: HashMap::new(), rarity: HashMap::new(), language: None };
+        let svg = render_svg("Test Zone", &treasures, None, &item_data, &PlotOptions::default()).expect("rendering svg");
+        assert!(svg.contains("<svg"));
+        assert!(svg.contains("Test Zone"));
+        assert_eq!(svg.matches("<circle").count(), treasures.len());
+        assert_eq!(svg.matches("<title>").count(), treasures.len());
+    }
+
+    #[test]
+    fn map_region_test() {
+        let treasures = vec![treasure(0, 0, 0), treasure(1, 120, 40), treasure(2, -60, 300)];
+        let item_data = ItemData { ids: HashMap::new(), rarity: HashMap::new(), language: None };
+        let opts = PlotOptions { map_region: Some(Bounds { min_x: -100, min_y: -100, max_x: 100, max_y: 100 }), ..PlotOptions::default() };
+        let svg = render_svg("Test Zone", &treasures, None, &item_data, &opts).expect("rendering svg");
+        assert_eq!(svg.matches("<circle").count(), 1);
+        assert!(svg.contains("2 chests outside the region"));
+    }
+
+    #[test]
+    fn plot_error_test() {
+        let item_data = ItemData { ids: HashMap::new(), rarity: HashMap::new(), language: None };
+        match render_svg("Empty Zone", &[], None, &item_data, &PlotOptions::default()) {
+            Err(PlotError::Coordinates(_)) => {},
+            res => panic!("expected a coordinate error, got {:?}", res),
+        }
+        let treasures = vec![treasure(0, -30000, 0), treasure(1, 30000, 0)];
+        let opts = PlotOptions { scale: 10.0, ..PlotOptions::default() };
+        assert!(matches!(render_svg("Huge Zone", &treasures, None, &item_data, &opts), Err(PlotError::Coordinates(_))));
+        assert!(matches!(catch_panic::<(), _>(|| panic!("bad zone")), Err(PlotError::Panic(msg)) if msg == "bad zone"));
+    }
+
+    #[test]
+    fn render_region_svg_test() {
+        let zones = vec![
+            RegionZone { name: String::from("West"), bounds: None, world_offset: (0, 0), treasures: vec![treasure(0, 0, 0), treasure(1, 50, 50)] },
+            RegionZone { name: String::from("East"), bounds: None, world_offset: (500, 0), treasures: vec![treasure(0, 0, 0)] },
+        ];
+        let item_data = ItemData { ids: HashMap::new(), rarity: HashMap::new(), language: None };
+        let svg = render_region_svg("Region", &zones, &item_data, &PlotOptions::default()).expect("rendering svg");
+        assert!(svg.contains("West"));
+        assert!(svg.contains("East"));
+        assert_eq!(svg.matches("<circle").count(), 3);
+    }
+
+    #[test]
+    fn nearest_route_test() {
+        let points = [(0, 0), (10, 0), (1, 1), (5, 0)];
+        assert_eq!(nearest_route(&points), vec![0, 2, 3, 1]);
+        assert!(nearest_route(&[]).is_empty());
+    }
 
+    #[test]
+    fn optimized_route_test() {
+        // Nearest neighbour heads up to 2 and 3 first, and has to cross back over to reach 4 and 5
+        let points = [(0, 0), (3, 0), (3, 3), (0, 4), (6, 1), (6, 4)];
+        let nearest = nearest_route(&points);
+        assert_eq!(nearest, vec![0, 1, 2, 3, 5, 4]);
+        let optimized = optimized_route(&points);
+        assert_eq!(optimized, vec![0, 1, 4, 5, 2, 3]);
+        assert!(route_length(&points, &optimized) < route_length(&points, &nearest));
+        assert!(optimized_route(&[]).is_empty());
+    }
 }
 
 //
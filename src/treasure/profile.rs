@@ -0,0 +1,32 @@
+use serde::Deserialize;
+
+/// Game-version specific assumptions about how treasure is interpreted.
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct Profile {
+    pub split: DropSplit,
+    /// What raw positions are divided by to give world units, for positions stored as fixed-point
+    pub position_scale: f64,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile { split: DropSplit::default(), position_scale: 1.0 }
+    }
+}
+
+/// Percentage chance of each item slot being chosen once the chest has decided to give an item.
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(default)]
+pub struct DropSplit {
+    pub first_item: u8,
+    pub second_item: u8,
+    pub rare_first_item: u8,
+    pub rare_second_item: u8,
+}
+
+impl Default for DropSplit {
+    fn default() -> Self {
+        DropSplit { first_item: 50, second_item: 50, rare_first_item: 95, rare_second_item: 5 }
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::error::TreasureError;
+use crate::opt::{ItemFormat, ItemListFormat, ReportFormat, RouteMode};
+use crate::treasure::{csv_field, get_data, get_datas, get_item_data, get_profile, parse_zones, ItemData, TreasureData, ZoneTreasure};
+use crate::treasure::plotter::{self, PlotOptions};
+use crate::treasure::slots::SlotBinds;
+
+fn print_json<T: Serialize>(value: &T) {
+    serde_json::to_writer_pretty(std::io::stdout(), value).expect("Writing to stdout");
+    println!();
+}
+
+pub fn used_items(input: PathBuf, treasure_data: PathBuf, item_data: Option<PathBuf>, item_format: ItemFormat, format: ItemListFormat, no_cache: bool) {
+    let treasure_data: TreasureData = get_data(treasure_data, "treasure", "TREASURE", !no_cache);
+    let item_data: Option<ItemData> = item_data.map(|pb| get_item_data(pb, item_format, !no_cache));
+    let ids = used_ids(input, &treasure_data);
+
+    let name_of = |id: u16| item_data.as_ref().and_then(|data| data.name(id)).map(String::as_str);
+    match format {
+        ItemListFormat::Text => {
+            for id in ids {
+                match name_of(id) {
+                    Some(name) => println!("{}\t{}", id, name),
+                    None => println!("{}", id),
+                }
+            }
+        },
+        ItemListFormat::Csv => {
+            println!("id,name");
+            for id in ids {
+                println!("{},{}", id, csv_field(name_of(id).unwrap_or("")));
+            }
+        },
+        ItemListFormat::Json => {
+            let list = ids.into_iter().map(|id| json!({ "id": id, "name": name_of(id) })).collect::<Vec<_>>();
+            print_json(&list);
+        }
+    }
+}
+
+fn used_ids(input: PathBuf, treasure_data: &TreasureData) -> BTreeSet<u16> {
+    let mut ids = BTreeSet::new();
+    for parsed in parse_zones(input, treasure_data) {
+        for treasure in &parsed.treasures {
+            ids.extend(treasure.all_items().iter().copied());
+        }
+    }
+    ids
+}
+
+pub fn scaffold_items(input: PathBuf, treasure_data: PathBuf, output: PathBuf, no_cache: bool) {
+    if output.exists() {
+        eprintln!("{:?} already exists. Choose another output so no item names are lost.", output);
+        std::process::exit(4);
+    }
+    let treasure_data: TreasureData = get_data(treasure_data, "treasure", "TREASURE", !no_cache);
+    let ids = used_ids(input, &treasure_data)
+        .into_iter()
+        .map(|id| (id, format!("TODO:0x{:04x}", id)))
+        .collect::<BTreeMap<_, _>>();
+
+    let res = File::create(&output).map_err(TreasureError::from)
+        .and_then(|file| Ok(serde_json::to_writer_pretty(BufWriter::new(file), &json!({ "ids": ids }))?));
+    if let Err(err) = res {
+        eprintln!("Unable to write {:?}. Error: {}", output, err);
+        std::process::exit(4);
+    }
+    eprintln!("Wrote {} item IDs to {:?}.", ids.len(), output);
+}
+
+#[derive(Serialize, Default, Debug)]
+struct Stats {
+    zones: usize,
+    chests: usize,
+    /// Chests without a respawn slot, which come back after leaving the area
+    respawning: usize,
+    total_gil: u64,
+    total_rare_gil: u64,
+    spawn_chances: BTreeMap<u8, usize>,
+    gil_chances: BTreeMap<u8, usize>,
+    respawn_slots: BTreeMap<u8, usize>,
+    top_items: Vec<ItemCount>,
+}
+
+#[derive(Serialize, Debug)]
+struct ItemCount {
+    id: u16,
+    name: Option<String>,
+    /// Number of item slots, across every chest, holding this item
+    count: usize,
+}
+
+pub fn stats(input: PathBuf, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, top: usize, format: ReportFormat, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let mut stats = Stats::default();
+    let mut item_counts = HashMap::new();
+    for parsed in parse_zones(input, &treasure_data) {
+        stats.zones += 1;
+        for treasure in &parsed.treasures {
+            stats.chests += 1;
+            stats.total_gil += treasure.gil_amount as u64;
+            stats.total_rare_gil += treasure.rare_gil_amount as u64;
+            *stats.spawn_chances.entry(treasure.spawn_chance).or_insert(0) += 1;
+            *stats.gil_chances.entry(treasure.gil_chance).or_insert(0) += 1;
+            if treasure.respawn_slot == 255 {
+                stats.respawning += 1;
+            } else {
+                *stats.respawn_slots.entry(treasure.respawn_slot).or_insert(0) += 1;
+            }
+            for id in treasure.all_items().iter() {
+                *item_counts.entry(*id).or_insert(0usize) += 1;
+            }
+        }
+    }
+
+    let mut item_counts = item_counts.into_iter().collect::<Vec<_>>();
+    item_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    stats.top_items = item_counts.into_iter()
+        .take(top)
+        .map(|(id, count)| ItemCount { id, name: item_data.name(id).cloned(), count })
+        .collect();
+
+    match format {
+        ReportFormat::Text => print_stats(&stats),
+        ReportFormat::Json => print_json(&stats),
+    }
+}
+
+fn print_stats(stats: &Stats) {
+    println!("Zones: {}", stats.zones);
+    println!("Chests: {} ({} respawning)", stats.chests, stats.respawning);
+    println!("Total gil: {} (Diamond Armlet: {})", stats.total_gil, stats.total_rare_gil);
+    println!("Spawn chance distribution:");
+    for (chance, count) in &stats.spawn_chances {
+        println!("\t{:>3}%: {}", chance, count);
+    }
+    println!("Gil chance distribution:");
+    for (chance, count) in &stats.gil_chances {
+        println!("\t{:>3}%: {}", chance, count);
+    }
+    println!("Chests per respawn slot:");
+    for (slot, count) in &stats.respawn_slots {
+        println!("\t{:02x}: {}", slot, count);
+    }
+    println!("Most common items:");
+    for item in &stats.top_items {
+        println!("\t{} ({}): {}", item.name.as_ref().map(String::as_str).unwrap_or("Unknown"), item.id, item.count);
+    }
+}
+
+pub fn respawn_slots(input: PathBuf, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, format: ReportFormat, hide_empty_slots: bool, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let mut slot_binds = SlotBinds::default();
+    for parsed in parse_zones(input, &treasure_data) {
+        for treasure in &parsed.treasures {
+            let item = match item_data.name(treasure.first_item) {
+                Some(name) => name.clone(),
+                None => format!("Unknown 0x{:04x}", treasure.first_item),
+            };
+            slot_binds.record(&parsed.zone.name, parsed.group, treasure, &item);
+        }
+    }
+
+    match format {
+        ReportFormat::Text => slot_binds.write_text(&mut std::io::stdout(), hide_empty_slots).expect("Writing to stdout"),
+        ReportFormat::Json => print_json(&slot_binds.to_json()),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Found<'a> {
+    group: &'a str,
+    zone: &'a str,
+    id: u32,
+    pos_x: i16,
+    pos_y: i16,
+    respawn_slot: u8,
+    spawn_chance: u8,
+    gil_chance: u8,
+    gil_amount: u16,
+    rare_gil_amount: u16,
+    items: [String; 4],
+}
+
+impl<'a> Found<'a> {
+    fn new(group: &'a str, zone: &'a str, treasure: &ZoneTreasure, item_data: &ItemData) -> Found<'a> {
+        let name = |id: u16| match item_data.name(id) {
+            Some(name) => name.clone(),
+            None => format!("Unknown 0x{:04x}", id),
+        };
+        let items = treasure.all_items();
+        Found {
+            group,
+            zone,
+            id: treasure.id,
+            pos_x: treasure.pos_x,
+            pos_y: treasure.pos_y,
+            respawn_slot: treasure.respawn_slot,
+            spawn_chance: treasure.spawn_chance,
+            gil_chance: treasure.gil_chance,
+            gil_amount: treasure.gil_amount,
+            rare_gil_amount: treasure.rare_gil_amount,
+            items: [name(items[0]), name(items[1]), name(items[2]), name(items[3])],
+        }
+    }
+
+    fn print_details(&self) {
+        println!("\tSlot {:x}, spawn {}%, gil {}% ({}, Diamond Armlet {})", self.respawn_slot, self.spawn_chance, self.gil_chance, self.gil_amount, self.rare_gil_amount);
+        println!("\tItems: {}, {}", self.items[0], self.items[1]);
+        println!("\tDiamond Armlet: {}, {}", self.items[2], self.items[3]);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn find(input: PathBuf, id: u32, zone: Option<String>, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, format: ReportFormat, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let zones = parse_zones(input, &treasure_data);
+    let mut found = Vec::new();
+    for parsed in zones.iter().filter(|parsed| zone.as_ref().map(|zone| zone == &parsed.zone.name).unwrap_or(true)) {
+        for treasure in parsed.treasures.iter().filter(|t| t.id == id) {
+            found.push(Found::new(parsed.group, &parsed.zone.name, treasure, &item_data));
+        }
+    }
+
+    match format {
+        ReportFormat::Text => {
+            if found.is_empty() {
+                eprintln!("No chest with ID {} was found.", id);
+            }
+            for f in &found {
+                println!("{}: {} :: {} at ({}, {})", f.group, f.zone, f.id, f.pos_x, f.pos_y);
+                f.print_details();
+            }
+        },
+        ReportFormat::Json => print_json(&found),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Nearby<'a> {
+    #[serde(flatten)]
+    chest: Found<'a>,
+    distance: f64,
+}
+
+/// Lists the zone's chests within `radius` of `(x, y)`, closest first.
+#[allow(clippy::too_many_arguments)]
+pub fn near(input: PathBuf, zone: String, x: i16, y: i16, radius: f64, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, format: ReportFormat, precision: usize, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let zones = parse_zones(input, &treasure_data);
+    let parsed = match zones.iter().find(|parsed| parsed.zone.name == zone) {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("No zone named {:?} was found in the input.", zone);
+            std::process::exit(4);
+        }
+    };
+    let mut nearby = parsed.treasures.iter()
+        .map(|t| (t, (t.pos_x as f64 - x as f64).hypot(t.pos_y as f64 - y as f64)))
+        .filter(|(_, distance)| *distance <= radius)
+        .map(|(t, distance)| Nearby { chest: Found::new(parsed.group, &parsed.zone.name, t, &item_data), distance })
+        .collect::<Vec<_>>();
+    nearby.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap());
+
+    match format {
+        ReportFormat::Text => {
+            if nearby.is_empty() {
+                eprintln!("No chest in {} is within {} of ({}, {}).", zone, radius, x, y);
+            }
+            for n in &nearby {
+                println!("{} at ({}, {}), {:.4$} away", n.chest.id, n.chest.pos_x, n.chest.pos_y, n.distance, precision);
+                n.chest.print_details();
+            }
+        },
+        ReportFormat::Json => print_json(&nearby),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Route<'a> {
+    zone: &'a str,
+    length: f64,
+    chests: Vec<Found<'a>>,
+}
+
+/// Plans a short route through every chest of the zone, starting from `start` or else its first chest: the
+/// nearest neighbour route improved with 2-opt.
+#[allow(clippy::too_many_arguments)]
+pub fn route(input: PathBuf, zone: String, start: Option<u32>, svg: Option<PathBuf>, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, format: ReportFormat, precision: usize, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+
+    let zones = parse_zones(input, &treasure_data);
+    let parsed = match zones.iter().find(|parsed| parsed.zone.name == zone) {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("No zone named {:?} was found in the input.", zone);
+            std::process::exit(4);
+        }
+    };
+    // Routes start from the first chest, so move the starting chest to the front
+    let mut treasures = parsed.treasures.clone();
+    if let Some(start) = start {
+        match treasures.iter().position(|t| t.id == start) {
+            Some(index) => treasures[..=index].rotate_right(1),
+            None => {
+                eprintln!("No chest with ID {} was found in {}.", start, zone);
+                std::process::exit(4);
+            }
+        }
+    }
+    let points = treasures.iter().map(|t| (t.pos_x as i32, t.pos_y as i32)).collect::<Vec<_>>();
+    let order = plotter::optimized_route(&points);
+    let length = plotter::route_length(&points, &order);
+
+    if let Some(svg_path) = svg {
+        let plot_opts = PlotOptions { route: Some(RouteMode::Optimized), ..PlotOptions::default() };
+        if let Err(err) = plotter::plot(&svg_path, &parsed.zone.name, &treasures, parsed.zone.bounds, &item_data, &plot_opts) {
+            eprintln!("Failed to write the route map to {:?}. Error: {}", svg_path, err);
+        }
+    }
+
+    let route = Route {
+        zone: &parsed.zone.name,
+        length,
+        chests: order.iter().map(|&i| Found::new(parsed.group, &parsed.zone.name, &treasures[i], &item_data)).collect(),
+    };
+    match format {
+        ReportFormat::Text => {
+            println!("{} chests in {}, {:.3$} in total:", route.chests.len(), zone, length, precision);
+            for (step, chest) in route.chests.iter().enumerate() {
+                println!("{:>3}. Chest {} at ({}, {})", step + 1, chest.id, chest.pos_x, chest.pos_y);
+            }
+        },
+        ReportFormat::Json => print_json(&route),
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Outcome {
+    outcome: String,
+    count: u32,
+    percent: f64,
+    expected_percent: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct Simulation<'a> {
+    chest: Found<'a>,
+    trials: u32,
+    seed: u64,
+    diamond_armlet: bool,
+    outcomes: Vec<Outcome>,
+}
+
+/// Rolls the chest's spawn, gil and item slot chances `trials` times with a seeded RNG and counts each
+/// outcome, next to the chance the profile's split says it should have.
+#[allow(clippy::too_many_arguments)]
+pub fn simulate(input: PathBuf, zone: String, id: u32, trials: u32, seed: Option<u64>, diamond_armlet: bool, profile: Option<PathBuf>, treasure_data: PathBuf, item_data: PathBuf, item_format: ItemFormat, format: ReportFormat, precision: usize, no_cache: bool) {
+    let (treasure_data, item_data) = get_datas(treasure_data, item_data, item_format, !no_cache);
+    let split = get_profile(profile).split;
+
+    let zones = parse_zones(input, &treasure_data);
+    let parsed = match zones.iter().find(|parsed| parsed.zone.name == zone) {
+        Some(parsed) => parsed,
+        None => {
+            eprintln!("No zone named {:?} was found in the input.", zone);
+            std::process::exit(4);
+        }
+    };
+    let treasure = match parsed.treasures.iter().find(|t| t.id == id) {
+        Some(treasure) => treasure,
+        None => {
+            eprintln!("No chest with ID {} was found in {}.", id, zone);
+            std::process::exit(4);
+        }
+    };
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let chest = Found::new(parsed.group, &parsed.zone.name, treasure, &item_data);
+    let (first, second, names, gil_amount) = if diamond_armlet {
+        (split.rare_first_item, split.rare_second_item, [&chest.items[2], &chest.items[3]], treasure.rare_gil_amount)
+    } else {
+        (split.first_item, split.second_item, [&chest.items[0], &chest.items[1]], treasure.gil_amount)
+    };
+    let weight = std::cmp::max(first as u32 + second as u32, 1);
+    let spawn_chance = std::cmp::min(treasure.spawn_chance, 100) as f64 / 100.0;
+    let gil_chance = std::cmp::min(treasure.gil_chance, 100) as f64 / 100.0;
+
+    // Not spawned, gil, first item, second item
+    let mut counts = [0u32; 4];
+    for _ in 0..trials {
+        let outcome = if rng.gen_range(0, 100) >= treasure.spawn_chance {
+            0
+        } else if rng.gen_range(0, 100) < treasure.gil_chance {
+            1
+        } else if rng.gen_range(0, weight) < first as u32 {
+            2
+        } else {
+            3
+        };
+        counts[outcome] += 1;
+    }
+
+    let expected = [
+        1.0 - spawn_chance,
+        spawn_chance * gil_chance,
+        spawn_chance * (1.0 - gil_chance) * first as f64 / weight as f64,
+        spawn_chance * (1.0 - gil_chance) * second as f64 / weight as f64,
+    ];
+    let labels = [
+        "Not spawned".to_string(),
+        format!("{} gil", gil_amount),
+        format!("Item 1: {}", names[0]),
+        format!("Item 2: {}", names[1]),
+    ];
+    let outcomes = labels.iter().zip(counts.iter()).zip(expected.iter())
+        .map(|((label, &count), &expected)| Outcome {
+            outcome: label.clone(),
+            count,
+            percent: if trials == 0 { 0.0 } else { count as f64 * 100.0 / trials as f64 },
+            expected_percent: expected * 100.0,
+        })
+        .collect();
+    let simulation = Simulation { chest, trials, seed, diamond_armlet, outcomes };
+
+    match format {
+        ReportFormat::Text => {
+            println!("Chest {} in {}, {} trials{} (seed {}):", id, zone, trials, if diamond_armlet { " with the Diamond Armlet" } else { "" }, seed);
+            for outcome in &simulation.outcomes {
+                println!("\t{:<40}{:>10}{:>9.4$}% (expected {:.4$}%)", outcome.outcome, outcome.count, outcome.percent, outcome.expected_percent, precision);
+            }
+        },
+        ReportFormat::Json => print_json(&simulation),
+    }
+}
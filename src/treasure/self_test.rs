@@ -0,0 +1,88 @@
+use std::io::Cursor;
+
+use byteorder::{WriteBytesExt, LE};
+use structopt::StructOpt;
+
+use crate::opt::DumpTreasure;
+use crate::treasure::{group_of, read_treasure_files, ItemData, TreasureData, UNKNOWN_GROUP};
+use crate::treasure::dump::Dump;
+
+const SAMPLE_TREASURE_DATA: &str = r#"{"groups":{"Sample":["sample_zone"]},"zones":{"sample_zone":{"name":"Sample Zone","offset":4,"quantity":2}}}"#;
+const SAMPLE_ITEM_DATA: &str = r#"{"ids":{"0":"Potion","1":"Hi-Potion","2":"Ether","3":"Elixir"}}"#;
+
+/// The text table for the sample zone, as dumped with the default profile.
+const EXPECTED: &str = concat!(
+    "Sample Zone\n",
+    "\tID Slot  Spn%  Gil%  Gil   Item 1 (50%)        Item 2 (50%)        DA 1 (95%)          DA 2 (5%)           DGil      X     Y\n",
+    "\t============================================================================================================================\n",
+    "\t0  1     80    50    120   Potion              Hi-Potion           Ether               Elixir                900   100   -50\n",
+    "\t1  ff    100   0     0     Potion              Potion              Potion              Potion                  0   -20   300\n",
+);
+
+/// Dumps the embedded sample zone in memory and compares the table against a known-good copy.
+/// Exits with 1 and a line by line diff if anything differs.
+pub fn self_test() {
+    let actual = match run() {
+        Ok(actual) => actual,
+        Err(err) => {
+            eprintln!("Self-test failed. Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if actual == EXPECTED {
+        println!("Self-test OK");
+        return;
+    }
+
+    eprintln!("Self-test failed. The dumped table differs from the expected one:");
+    let expected_lines = EXPECTED.lines().collect::<Vec<_>>();
+    let actual_lines = actual.lines().collect::<Vec<_>>();
+    for i in 0..std::cmp::max(expected_lines.len(), actual_lines.len()) {
+        let (expected, actual) = (expected_lines.get(i), actual_lines.get(i));
+        if expected != actual {
+            eprintln!("line {}:", i + 1);
+            eprintln!("-{}", expected.unwrap_or(&""));
+            eprintln!("+{}", actual.unwrap_or(&""));
+        }
+    }
+    std::process::exit(1);
+}
+
+fn run() -> Result<String, Box<dyn std::error::Error>> {
+    let treasure_data: TreasureData = serde_json::from_str(SAMPLE_TREASURE_DATA)?;
+    let item_data: ItemData = serde_json::from_str(SAMPLE_ITEM_DATA)?;
+    let zone = treasure_data.zones.get("sample_zone").ok_or("sample zone missing from the sample data")?;
+    let group = group_of(&treasure_data, "sample_zone", UNKNOWN_GROUP);
+    let treasures = read_treasure_files(Cursor::new(sample_script()?), zone)?;
+
+    let opts = DumpTreasure::from_iter_safe(&["self-test", "-", "--color", "never"])?;
+    let mut dump = Dump::new(&opts, &item_data);
+    let rows = dump.resolve_rows(group, &zone.name, &treasures);
+    let mut out = Vec::new();
+    dump.write_text(&mut out, group, &zone.name, &treasures, &rows, false, false)?;
+    Ok(String::from_utf8(out)?)
+}
+
+/// A zone script holding two treasure records after a few bytes of padding.
+fn sample_script() -> std::io::Result<Vec<u8>> {
+    let mut script = vec![0u8; 4];
+    // id, x, y, unknown, slot, spawn%, gil%, items, gil, diamond armlet gil
+    for &(id, x, y, slot, spawn, gil_chance, items, gil, rare_gil) in &[
+        (0u32, 100i16, -50i16, 1u8, 80u8, 50u8, [0u16, 1, 2, 3], 120u16, 900u16),
+        (1, -20, 300, 255, 100, 0, [0, 0, 0, 0], 0, 0),
+    ] {
+        script.write_u32::<LE>(id)?;
+        script.write_i16::<LE>(x)?;
+        script.write_i16::<LE>(y)?;
+        script.write_u8(0)?;
+        script.write_u8(slot)?;
+        script.write_u8(spawn)?;
+        script.write_u8(gil_chance)?;
+        for &item in &items {
+            script.write_u16::<LE>(item)?;
+        }
+        script.write_u16::<LE>(gil)?;
+        script.write_u16::<LE>(rare_gil)?;
+    }
+    Ok(script)
+}
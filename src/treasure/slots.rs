@@ -0,0 +1,86 @@
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::treasure::ZoneTreasure;
+
+/// A chest bound to a respawn slot.
+#[derive(Serialize, Debug)]
+pub struct SlotBind {
+    pub zone: String,
+    pub group: String,
+    pub id: u32,
+    /// Name of the chest's first common item
+    pub item: String,
+}
+
+/// Chests grouped by the respawn slot they share. Chests without a slot (255) are left out.
+#[derive(Serialize, Debug)]
+pub struct SlotBinds(Vec<Vec<SlotBind>>);
+
+impl Default for SlotBinds {
+    fn default() -> Self {
+        let mut slots = Vec::with_capacity(255);
+        for _ in 0..255 { slots.push(Vec::new()); }
+        SlotBinds(slots)
+    }
+}
+
+impl SlotBinds {
+    pub fn record(&mut self, zone: &str, group: &str, treasure: &ZoneTreasure, item: &str) {
+        if treasure.respawn_slot != 255 {
+            self.0[treasure.respawn_slot as usize].push(SlotBind {
+                zone: zone.to_string(),
+                group: group.to_string(),
+                id: treasure.id,
+                item: item.to_string(),
+            });
+        }
+    }
+
+    /// Writes the respawn-slots.txt format, one line per slot from 00 to fe. `hide_empty` leaves out
+    /// slots no chest is bound to.
+    pub fn write_text<W: Write>(&self, writer: &mut W, hide_empty: bool) -> std::io::Result<()> {
+        writeln!(writer, "Slots 00..fe{}. Chests with slot ff have none, and aren't listed.", if hide_empty { " with chests bound" } else { "" })?;
+        writeln!(writer, "Slot => [(Zone: Area :: ID = Item), (...), ...]")?;
+        for (i, slot) in self.0.iter().enumerate().filter(|(_, slot)| !hide_empty || !slot.is_empty()) {
+            write!(writer, "{:02x} => [", i)?;
+            for (k, bind) in slot.iter().enumerate() {
+                write!(writer, "({}: {} :: {} = {}){}", bind.group, bind.zone, bind.id, bind.item, if k == slot.len() - 1 { "" } else { ", " })?;
+            }
+            writeln!(writer, "]")?;
+        }
+        writeln!(writer, "Free slots: {}", self.free_slots().join(", "))?;
+        writer.flush()
+    }
+
+    /// The slots as `{ slots, free_slots }`. Slots is an array indexed by slot, each holding its chests as
+    /// `{ zone, group, id, item }`, and free_slots lists the slots without any like "0x03" or "0x07-0x09".
+    pub fn to_json(&self) -> Value {
+        json!({
+            "slots": self.0,
+            "free_slots": self.free_slots(),
+        })
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        serde_json::to_writer(&mut *writer, &self.to_json())?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// The slots no chest is bound to, with runs of consecutive slots merged into ranges.
+    fn free_slots(&self) -> Vec<String> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (i, _) in self.0.iter().enumerate().filter(|(_, slot)| slot.is_empty()) {
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == i => *last = i,
+                _ => ranges.push((i, i)),
+            }
+        }
+        ranges.into_iter().map(|(first, last)| {
+            if first == last { format!("0x{:02x}", first) } else { format!("0x{:02x}-0x{:02x}", first, last) }
+        }).collect()
+    }
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use crate::treasure::ItemData;
+use crate::treasure::dump::Snapshot;
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE zones (
+        id INTEGER PRIMARY KEY,
+        group_name TEXT NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE TABLE items (
+        id INTEGER PRIMARY KEY,
+        name TEXT
+    );
+    CREATE TABLE treasures (
+        zone_id INTEGER NOT NULL REFERENCES zones(id),
+        id INTEGER NOT NULL,
+        pos_x INTEGER NOT NULL,
+        pos_y INTEGER NOT NULL,
+        respawn_slot INTEGER NOT NULL,
+        spawn_chance INTEGER NOT NULL,
+        gil_chance INTEGER NOT NULL,
+        first_item INTEGER NOT NULL REFERENCES items(id),
+        second_item INTEGER NOT NULL REFERENCES items(id),
+        rare_first_item INTEGER NOT NULL REFERENCES items(id),
+        rare_second_item INTEGER NOT NULL REFERENCES items(id),
+        gil_amount INTEGER NOT NULL,
+        rare_gil_amount INTEGER NOT NULL
+    );
+";
+
+/// Writes every dumped zone to a new database at `path`, replacing any file already there.
+/// Items referenced by a treasure but missing from the item data are kept, with a NULL name.
+pub fn write_database(path: &Path, snapshot: &Snapshot, item_data: &ItemData) -> Result<(), Box<dyn Error>> {
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let tx = conn.transaction()?;
+    {
+        let mut item_ids = item_data.ids.keys().copied().collect::<BTreeSet<u32>>();
+        for (_, _, treasures) in snapshot {
+            item_ids.extend(treasures.iter().flat_map(|t| t.all_items().to_vec()).map(u32::from));
+        }
+        let mut insert_item = tx.prepare("INSERT INTO items (id, name) VALUES (?1, ?2)")?;
+        for id in item_ids {
+            insert_item.execute(params![id, item_data.ids.get(&id)])?;
+        }
+
+        let mut insert_zone = tx.prepare("INSERT INTO zones (group_name, name) VALUES (?1, ?2)")?;
+        let mut insert_treasure = tx.prepare(
+            "INSERT INTO treasures VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)"
+        )?;
+        for (group, name, treasures) in snapshot {
+            insert_zone.execute(params![group, name])?;
+            let zone_id = tx.last_insert_rowid();
+            for t in treasures {
+                insert_treasure.execute(params![
+                    zone_id, t.id, t.pos_x, t.pos_y, t.respawn_slot, t.spawn_chance, t.gil_chance,
+                    t.first_item, t.second_item, t.rare_first_item, t.rare_second_item, t.gil_amount, t.rare_gil_amount,
+                ])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
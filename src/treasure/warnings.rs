@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::error::TreasureError;
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    UnknownItem,
+    MissingZone,
+    BadChance,
+    DuplicateId,
+    /// Every treasure in the zone shares one position, so no map was drawn
+    StackedPositions,
+    /// The treasure lies outside the zone's known bounds, so it was probably misparsed
+    OutOfBounds,
+    /// A group lists a file stem that has no zone data
+    OrphanedGroupEntry,
+    /// The zone has no world offset, so it was left off its group's merged map
+    MissingWorldOffset,
+    /// The chest has a 0% spawn chance, so it never appears
+    Unspawnable,
+    /// Several chests in the zone are at exactly the same position
+    SharedPosition,
+    /// The chest can give an item, but a slot that can be picked holds item ID 0
+    EmptyItem,
+    /// The group or zone name can't be used as a file name as is, so its output was written under an adjusted one
+    RenamedFile,
+    /// The zone's map couldn't be drawn, so it was skipped and the dump carried on
+    MapFailed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub zone: Option<String>,
+    pub id: Option<u32>,
+    pub detail: String,
+}
+
+/// Collects every warning raised during a dump so they can be written out together.
+#[derive(Default, Debug)]
+pub struct Warnings {
+    list: Vec<Warning>,
+    /// Color the "Warning:" prefix on stderr
+    color: bool,
+}
+
+impl Warnings {
+    pub fn new(color: bool) -> Warnings {
+        Warnings { list: Vec::new(), color }
+    }
+
+    /// Records a warning and reports it on stderr.
+    pub fn warn(&mut self, kind: WarningKind, zone: Option<&str>, id: Option<u32>, detail: String) {
+        let prefix = if self.color { "\x1b[1;33mWarning:\x1b[0m" } else { "Warning:" };
+        match (zone, id) {
+            (Some(zone), Some(id)) => eprintln!("{} {} (zone {}, id {})", prefix, detail, zone, id),
+            (Some(zone), None) => eprintln!("{} {} (zone {})", prefix, detail, zone),
+            _ => eprintln!("{} {}", prefix, detail),
+        }
+        self.record(kind, zone, id, detail);
+    }
+
+    /// Records a warning without reporting it, for conditions too common to print every time.
+    pub fn record(&mut self, kind: WarningKind, zone: Option<&str>, id: Option<u32>, detail: String) {
+        self.list.push(Warning { kind, zone: zone.map(String::from), id, detail });
+    }
+
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> Result<(), TreasureError> {
+        serde_json::to_writer_pretty(File::create(path)?, &self.list)?;
+        Ok(())
+    }
+}
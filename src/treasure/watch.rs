@@ -0,0 +1,103 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+use crate::opt::DumpTreasure;
+use crate::treasure::data_paths;
+use crate::utils;
+
+/// How long the watched files must be quiet before dumping again.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Dumps, then dumps again each time the input or data files change, until interrupted.
+/// Each dump runs as a child process with the same arguments, so a dump that fails (say, on a
+/// half-saved data file) doesn't end the watch.
+pub fn watch(opts: &DumpTreasure) {
+    let (treasure_path, item_path) = data_paths(opts);
+    let (tx, rx) = channel();
+    let mut watcher = match watcher(tx, DEBOUNCE) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("Unable to watch for changes. Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    for (path, mode) in &[(opts.input(), RecursiveMode::Recursive), (&treasure_path, RecursiveMode::NonRecursive), (&item_path, RecursiveMode::NonRecursive)] {
+        if let Err(err) = watcher.watch(path, *mode) {
+            eprintln!("Unable to watch {:?}. Error: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+
+    // Changes the dumps make themselves would otherwise set off another dump, forever when the output is inside the input
+    let written = [opts.output.as_ref(), opts.maps_html.as_ref(), opts.warnings_json.as_ref(), opts.slots_output.as_ref()].iter()
+        .flatten()
+        .map(|path| absolute(path))
+        .collect::<Vec<_>>();
+
+    let args = std::env::args_os().skip(1).filter(|arg| arg != "--watch").collect::<Vec<OsString>>();
+    utils::defer_interrupts();
+    run_dump(&args);
+    loop {
+        match rx.recv_timeout(Duration::from_millis(200)) {
+            // Notices arrive as soon as something starts changing; the settled event follows the debounce.
+            Ok(DebouncedEvent::NoticeWrite(_)) | Ok(DebouncedEvent::NoticeRemove(_)) => {},
+            Ok(DebouncedEvent::Error(err, path)) => eprintln!("Error while watching {:?}. Error: {}", path, err),
+            Ok(event) if is_written(&event, &written) => {},
+            Ok(_) => {
+                while rx.try_recv().is_ok() {}
+                run_dump(&args);
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if utils::interrupted() {
+            break;
+        }
+    }
+}
+
+/// Whether every path the event is about lies under one of the dump's own outputs.
+fn is_written(event: &DebouncedEvent, written: &[PathBuf]) -> bool {
+    let paths = match event {
+        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Chmod(path) | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        _ => return false,
+    };
+    paths.into_iter().all(|path| {
+        let path = absolute(path);
+        written.iter().any(|output| path.starts_with(output))
+    })
+}
+
+/// The path made absolute, resolving links where it exists. Outputs not written yet resolve through their parent.
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    match (path.parent().and_then(|parent| parent.canonicalize().ok()), path.file_name()) {
+        (Some(parent), Some(name)) => parent.join(name),
+        _ => std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+fn run_dump(args: &[OsString]) {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    eprintln!("==== Dumping at {:02}:{:02}:{:02} UTC ====", now / 3600 % 24, now / 60 % 60, now % 60);
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            eprintln!("Unable to find this executable to run the dump. Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    match Command::new(exe).args(args).status() {
+        Ok(status) if !status.success() => eprintln!("Dump failed ({}). Waiting for changes.", status),
+        Ok(_) => eprintln!("Dump finished. Waiting for changes."),
+        Err(err) => eprintln!("Unable to run the dump. Error: {}", err),
+    }
+}
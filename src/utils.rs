@@ -1,7 +1,55 @@
 use std::io::{Seek, Read, SeekFrom};
+use std::sync::Once;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 const BUFFER_SIZE: usize = 4096;
 
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static HANDLER: Once = Once::new();
+
+/// Replaces the default Ctrl-C behaviour with a flag, so long running work can stop at a safe point.
+/// Callers are expected to poll `interrupted`. Calling this again is harmless.
+pub fn defer_interrupts() {
+    HANDLER.call_once(|| {
+        if let Err(err) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+            eprintln!("Unable to install Ctrl-C handler. Error: {}", err);
+        }
+    });
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Device names Windows reserves in every directory, whatever extension follows them.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Makes `name` usable as a file name on every platform. Characters Windows doesn't allow and trailing
+/// dots and spaces become `_`, and reserved device names such as CON get a `_` after them.
+pub fn safe_file_name(name: &str) -> String {
+    let mut safe = name.chars()
+        .map(|c| if c.is_control() || "<>:\"/\\|?*".contains(c) { '_' } else { c })
+        .collect::<String>();
+    let kept = safe.trim_end_matches(|c| c == '.' || c == ' ').len();
+    let trailing = safe.len() - kept;
+    safe.truncate(kept);
+    safe.extend(std::iter::repeat('_').take(trailing));
+    let stem = safe.find('.').unwrap_or_else(|| safe.len());
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(safe[..stem].trim_end())) {
+        safe.insert(stem, '_');
+    }
+    if safe.is_empty() {
+        safe.push('_');
+    }
+    safe
+}
+
 pub fn locate_signature<R: Read + Seek>(reader: &mut R, signature: &[u8]) -> Option<usize> {
     assert!(signature.len() > 1, "Can't locate an empty signature!");
     assert!(signature.len() < BUFFER_SIZE, "Signature is too long for buffer!");
@@ -55,7 +103,7 @@ fn find_in_slice(slice: &[u8], signature: &[u8]) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::{locate_signature, find_in_slice};
+    use crate::utils::{locate_signature, find_in_slice, safe_file_name};
     use std::io::Cursor;
     use rand::Rng;
 
@@ -114,4 +162,15 @@ mod tests {
         assert_eq!(locate_signature(&mut cursor, &SIGNATURE[..]).expect("not found"), 4092);
     }
 
+    #[test]
+    fn test_safe_file_name() {
+        assert_eq!(safe_file_name("Rabanastre"), "Rabanastre");
+        assert_eq!(safe_file_name("con"), "con_");
+        assert_eq!(safe_file_name("NUL.txt"), "NUL_.txt");
+        assert_eq!(safe_file_name("COM10"), "COM10");
+        assert_eq!(safe_file_name("Ends in dots.. "), "Ends in dots___");
+        assert_eq!(safe_file_name("What? A/B"), "What_ A_B");
+        assert_eq!(safe_file_name(""), "_");
+    }
+
 }